@@ -0,0 +1,22 @@
+use litaudio::*;
+use num_traits::ToPrimitive;
+use crate::samples::*;
+
+pub const DC_OFFSET_THRESHOLD: f64 = 0.01;
+
+pub fn dc_offset<T, P, S>(audio: &S) -> Vec<f64>
+	where T: Sample + ToPrimitive, P: SamplePackingType, S: AudioStorage<T, P>
+{
+	(0..audio.channels()).map(|c| {
+		match audio.samples() {
+			0 => 0.,
+			n => channel_iter(audio, c).map(to_normalized).sum::<f64>() / n as f64
+		}
+	}).collect()
+}
+
+pub fn has_dc_offset<T, P, S>(audio: &S, threshold: f64) -> bool
+	where T: Sample + ToPrimitive, P: SamplePackingType, S: AudioStorage<T, P>
+{
+	dc_offset(audio).iter().any(|o| o.abs() > threshold)
+}
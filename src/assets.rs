@@ -0,0 +1,123 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use litaudio::*;
+use litcontainers::*;
+use crate::error::Error;
+use crate::options::{ReaderOptions, WriterOptions};
+use crate::dataset::Dataset;
+use crate::{read_audio_with, write_audio_with};
+
+// Target format of a platform, outputs are written to `<out>/<name>/<relative path>.<extension>`
+#[derive(Clone, Debug)]
+pub struct Profile {
+	pub name: String,
+	pub extension: String,
+	pub sample_rate: Option<i32>,
+	pub options: WriterOptions,
+}
+
+impl Profile {
+	pub fn new(name: &str, extension: &str, options: WriterOptions) -> Self {
+		Profile { name: name.to_string(), extension: extension.to_string(), sample_rate: None, options }
+	}
+
+	pub fn sample_rate(mut self, sample_rate: i32) -> Self {
+		self.sample_rate = Some(sample_rate);
+		self
+	}
+
+	pub fn android() -> Self { Self::new("android", "ogg", WriterOptions::new().vorbis(4.)) }
+
+	pub fn console_adpcm() -> Self { Self::new("console", "wav", WriterOptions::new().codec("adpcm_ms")) }
+
+	pub fn pcm() -> Self { Self::new("pcm", "wav", WriterOptions::new().codec("pcm_s16le")) }
+}
+
+#[derive(Clone, Debug)]
+pub struct ManifestEntry {
+	pub source: PathBuf,
+	pub profile: String,
+	pub output: PathBuf,
+	pub duration: f64,
+	// FNV-1a of the output file
+	pub hash: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Manifest {
+	pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+	pub fn to_json(&self) -> String {
+		let entries: Vec<String> = self.entries.iter().map(|e| format!(
+			"\t\t{{\"source\": {}, \"profile\": {}, \"output\": {}, \"duration\": {}, \"hash\": \"{:016x}\"}}",
+			json_string(&e.source.to_string_lossy()), json_string(&e.profile),
+			json_string(&e.output.to_string_lossy()), e.duration, e.hash
+		)).collect();
+		format!("{{\n\t\"entries\": [\n{}\n\t]\n}}\n", entries.join(",\n"))
+	}
+
+	pub fn write(&self, path: &Path) -> Result<(), Error> {
+		fs::write(path, self.to_json()).map_err(|e| Error::from(format!("Could not write {}: {}", path.display(), e)))
+	}
+}
+
+// Converts every file in `src` matching `pattern` for each profile, sources failing to convert abort the run
+pub fn convert_assets(src: &Path, pattern: &str, out: &Path, profiles: &[Profile]) -> Result<Manifest, Error> {
+	let mut manifest = Manifest::default();
+
+	for item in Dataset::scan(src, pattern)?.iter() {
+		let relative = item.path().strip_prefix(src).unwrap_or(item.path());
+
+		for profile in profiles {
+			let output = out.join(&profile.name).join(relative).with_extension(&profile.extension);
+			if let Some(parent) = output.parent() {
+				fs::create_dir_all(parent)
+					.map_err(|e| Error::from(format!("Could not create {}: {}", parent.display(), e)))?;
+			}
+
+			let audio: AudioDeinterleaved<f32, Dynamic, Dynamic> = read_audio_with(
+				item.path(), ReaderOptions::new().target_sample_rate(profile.sample_rate)
+			)?;
+			write_audio_with(&output, &audio, profile.options.clone())?;
+
+			let data = fs::read(&output).map_err(|e| Error::from(format!("Could not read {}: {}", output.display(), e)))?;
+			manifest.entries.push(ManifestEntry {
+				source: item.path().to_path_buf(),
+				profile: profile.name.clone(),
+				duration: audio.samples() as f64 / audio.sample_rate() as f64,
+				hash: fnv1a(&data),
+				output,
+			});
+		}
+	}
+
+	Ok(manifest)
+}
+
+pub(crate) const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+fn fnv1a(data: &[u8]) -> u64 { fnv1a_extend(FNV_OFFSET, data) }
+
+// Continues a hash over more data, so large inputs can be hashed in pieces
+pub(crate) fn fnv1a_extend(hash: u64, data: &[u8]) -> u64 {
+	data.iter().fold(hash, |h, b| (h ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3))
+}
+
+fn json_string(s: &str) -> String {
+	let mut out = String::with_capacity(s.len() + 2);
+	out.push('"');
+	for c in s.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\t' => out.push_str("\\t"),
+			c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+			c => out.push(c),
+		}
+	}
+	out.push('"');
+	out
+}
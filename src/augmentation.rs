@@ -0,0 +1,123 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::ffmpeg::escape_filter_option;
+
+#[derive(Clone, Debug, Default)]
+pub struct Augmentation {
+	pub gain_db: Option<(f64, f64)>,
+	pub tempo: Option<(f64, f64)>,
+	pub pitch_semitones: Option<(f64, f64)>,
+	pub noise: Option<Noise>,
+	pub seed: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Noise {
+	pub path: String,
+	pub gain_db: (f64, f64),
+}
+
+impl Augmentation {
+	pub fn new() -> Self { Self::default() }
+
+	pub fn gain_db(mut self, min: f64, max: f64) -> Self {
+		self.gain_db = Some((min, max));
+		self
+	}
+
+	pub fn tempo(mut self, min: f64, max: f64) -> Self {
+		self.tempo = Some((min, max));
+		self
+	}
+
+	pub fn pitch_semitones(mut self, min: f64, max: f64) -> Self {
+		self.pitch_semitones = Some((min, max));
+		self
+	}
+
+	// Mixes in a second input, e.g. a background noise recording
+	pub fn noise(mut self, path: &str, min_db: f64, max_db: f64) -> Self {
+		self.noise = Some(Noise { path: path.to_string(), gain_db: (min_db, max_db) });
+		self
+	}
+
+	// Fixes the random draws, without a seed every read is augmented differently
+	pub fn seed(mut self, seed: u64) -> Self {
+		self.seed = Some(seed);
+		self
+	}
+
+	pub(crate) fn filter_description(&self, sample_rate: i32) -> Option<String> {
+		let mut rng = Rng::new(self.seed.unwrap_or_else(|| {
+			SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0)
+		}));
+		let mut filters = Vec::new();
+
+		if let Some((min, max)) = self.gain_db {
+			filters.push(format!("volume={:.3}dB", rng.range(min, max)));
+		}
+
+		if let Some((min, max)) = self.pitch_semitones {
+			// Resampling shifts pitch and tempo together, atempo restores the tempo
+			let factor = 2f64.powf(rng.range(min, max) / 12.);
+			filters.push(format!(
+				"asetrate={},aresample={},{}",
+				(sample_rate as f64 * factor).round() as i32, sample_rate, atempo_chain(1. / factor)
+			));
+		}
+
+		if let Some((min, max)) = self.tempo {
+			filters.push(format!("atempo={:.6}", rng.range(min, max).max(0.5).min(2.)));
+		}
+
+		let chain = filters.join(",");
+		match self.noise {
+			None if chain.is_empty() => None,
+			None => Some(chain),
+			Some(ref noise) => {
+				let input = match chain.is_empty() {
+					true => "[in]".to_string(),
+					false => format!("[in]{}[aug];[aug]", chain)
+				};
+				// amix divides every input by the input count (FFmpeg 4 before 4.4 has no normalize=0),
+				// the looped noise never ends so the volume after it restores unity gain
+				Some(format!(
+					"amovie={}:loop=0,volume={:.3}dB[noise];{}[noise]amix=inputs=2:duration=first:dropout_transition=0,volume=2",
+					escape_filter_option(&noise.path), rng.range(noise.gain_db.0, noise.gain_db.1), input
+				))
+			}
+		}
+	}
+}
+
+// A single atempo accepts 0.5 to 2, larger changes are split into a chain of stages
+fn atempo_chain(mut tempo: f64) -> String {
+	let mut stages = Vec::new();
+	while tempo > 2. && tempo.is_finite() {
+		stages.push("atempo=2".to_string());
+		tempo /= 2.;
+	}
+	while tempo < 0.5 && tempo > 0. {
+		stages.push("atempo=0.5".to_string());
+		tempo /= 0.5;
+	}
+	stages.push(format!("atempo={:.6}", tempo));
+	stages.join(",")
+}
+
+struct Rng(u64);
+
+impl Rng {
+	fn new(seed: u64) -> Self { Rng(seed ^ 0x9E37_79B9_7F4A_7C15) }
+
+	fn next(&mut self) -> u64 {
+		// xorshift64*
+		self.0 ^= self.0 >> 12;
+		self.0 ^= self.0 << 25;
+		self.0 ^= self.0 >> 27;
+		self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+	}
+
+	fn range(&mut self, min: f64, max: f64) -> f64 {
+		min + (self.next() >> 11) as f64 / (1u64 << 53) as f64 * (max - min)
+	}
+}
@@ -0,0 +1,129 @@
+use std::ffi::CStr;
+use std::ptr;
+use libc::{c_char, c_void};
+use crate::sys::*;
+
+// Version of a linked libav* library
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct LibraryVersion {
+	pub name: &'static str,
+	pub major: u32,
+	pub minor: u32,
+	pub micro: u32,
+}
+
+impl LibraryVersion {
+	fn new(name: &'static str, version: u32) -> Self {
+		LibraryVersion { name, major: version >> 16, minor: version >> 8 & 0xff, micro: version & 0xff }
+	}
+}
+
+// What the linked FFmpeg build provides, names are those FFmpeg uses such as "libopus" or "matroska"
+#[derive(Clone, Debug)]
+pub struct BuildInfo {
+	pub libraries: Vec<LibraryVersion>,
+	// The ./configure flags of libavcodec
+	pub configuration: String,
+	// Audio decoders and encoders
+	pub decoders: Vec<String>,
+	pub encoders: Vec<String>,
+	pub demuxers: Vec<String>,
+	pub muxers: Vec<String>,
+	pub input_protocols: Vec<String>,
+	pub output_protocols: Vec<String>,
+}
+
+impl BuildInfo {
+	pub fn has_decoder(&self, name: &str) -> bool { contains(&self.decoders, name) }
+
+	pub fn has_encoder(&self, name: &str) -> bool { contains(&self.encoders, name) }
+
+	// Demuxer names may be lists such as "mov,mp4,m4a,3gp,3g2,mj2", each of them matches
+	pub fn has_demuxer(&self, name: &str) -> bool {
+		self.demuxers.iter().any(|d| d.split(',').any(|n| n == name))
+	}
+
+	pub fn has_muxer(&self, name: &str) -> bool { contains(&self.muxers, name) }
+
+	pub fn has_protocol(&self, name: &str) -> bool {
+		contains(&self.input_protocols, name) || contains(&self.output_protocols, name)
+	}
+}
+
+// Queries the linked FFmpeg, so missing codecs or protocols can be reported at startup
+pub fn build_info() -> BuildInfo {
+	unsafe {
+		let mut decoders = Vec::new();
+		let mut encoders = Vec::new();
+		let mut opaque = ptr::null_mut();
+		loop {
+			let codec = av_codec_iterate(&mut opaque);
+			if codec.is_null() {
+				break;
+			}
+			if (*codec).type_ != AVMediaType::AVMEDIA_TYPE_AUDIO {
+				continue;
+			}
+			match av_codec_is_encoder(codec) != 0 {
+				true => encoders.push(string((*codec).name)),
+				false => decoders.push(string((*codec).name)),
+			}
+		}
+
+		let mut demuxers = Vec::new();
+		let mut opaque = ptr::null_mut();
+		loop {
+			let format = av_demuxer_iterate(&mut opaque);
+			if format.is_null() {
+				break;
+			}
+			demuxers.push(string((*format).name));
+		}
+
+		let mut muxers = Vec::new();
+		let mut opaque = ptr::null_mut();
+		loop {
+			let format = av_muxer_iterate(&mut opaque);
+			if format.is_null() {
+				break;
+			}
+			muxers.push(string((*format).name));
+		}
+
+		BuildInfo {
+			libraries: vec![
+				LibraryVersion::new("libavutil", avutil_version()),
+				LibraryVersion::new("libavcodec", avcodec_version()),
+				LibraryVersion::new("libavformat", avformat_version()),
+				LibraryVersion::new("libavfilter", avfilter_version()),
+				LibraryVersion::new("libswresample", swresample_version()),
+			],
+			configuration: string(avcodec_configuration()),
+			decoders, encoders, demuxers, muxers,
+			input_protocols: protocols(false),
+			output_protocols: protocols(true),
+		}
+	}
+}
+
+unsafe fn protocols(output: bool) -> Vec<String> {
+	let mut protocols = Vec::new();
+	let mut opaque: *mut c_void = ptr::null_mut();
+	loop {
+		let name = avio_enum_protocols(&mut opaque, output as i32);
+		if name.is_null() {
+			break;
+		}
+		protocols.push(string(name));
+	}
+	protocols
+}
+
+unsafe fn string(s: *const c_char) -> String {
+	match s.is_null() {
+		true => String::new(),
+		false => CStr::from_ptr(s).to_string_lossy().into_owned()
+	}
+}
+
+fn contains(names: &[String], name: &str) -> bool { names.iter().any(|n| n == name) }
@@ -0,0 +1,98 @@
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use crate::error::Error;
+use crate::loudness::LoudnessMeasurement;
+
+// Broadcast Wave `bext` fields, written by the FFmpeg WAV muxer from the container metadata
+#[derive(Clone, Debug, Default)]
+pub struct Bext {
+	pub description: Option<String>,
+	pub originator: Option<String>,
+	pub originator_reference: Option<String>,
+	// yyyy-mm-dd
+	pub origination_date: Option<String>,
+	// hh:mm:ss
+	pub origination_time: Option<String>,
+	// Samples since midnight of the first sample
+	pub time_reference: Option<u64>,
+	pub coding_history: Option<String>,
+}
+
+impl Bext {
+	pub(crate) fn metadata(&self) -> Vec<(String, String)> {
+		let fields = [
+			("description", &self.description), ("originator", &self.originator),
+			("originator_reference", &self.originator_reference), ("origination_date", &self.origination_date),
+			("origination_time", &self.origination_time), ("coding_history", &self.coding_history),
+		];
+		let mut metadata: Vec<(String, String)> = fields.iter()
+			.filter_map(|(k, v)| v.as_ref().map(|v| (k.to_string(), v.clone())))
+			.collect();
+		if let Some(time_reference) = self.time_reference {
+			metadata.push(("time_reference".to_string(), time_reference.to_string()));
+		}
+		metadata
+	}
+}
+
+// The EBU Tech 3285 v2 loudness fields, in LUFS, LU and dBTP
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BextLoudness {
+	pub integrated: f64,
+	pub range: f64,
+	pub max_true_peak: f64,
+	pub max_momentary: f64,
+	pub max_short_term: f64,
+}
+
+impl From<&LoudnessMeasurement> for BextLoudness {
+	fn from(m: &LoudnessMeasurement) -> Self {
+		BextLoudness {
+			integrated: m.integrated,
+			range: m.lra,
+			max_true_peak: m.true_peak,
+			max_momentary: m.max_momentary,
+			max_short_term: m.max_short_term,
+		}
+	}
+}
+
+// Offset of the version field in the bext data, the loudness fields follow the 64 byte UMID
+const VERSION_OFFSET: u64 = 346;
+const LOUDNESS_OFFSET: u64 = 412;
+
+// FFmpeg writes version 1 bext chunks, this upgrades one in place to version 2 with the loudness fields
+pub(crate) fn write_loudness(path: &Path, loudness: &BextLoudness) -> Result<(), Error> {
+	let map_err = |e: std::io::Error| Error::from(format!("Could not update {}: {}", path.display(), e));
+	let mut file = OpenOptions::new().read(true).write(true).open(path).map_err(map_err)?;
+
+	file.seek(SeekFrom::Start(12)).map_err(map_err)?;
+	let mut chunk = [0u8; 8];
+	loop {
+		file.read_exact(&mut chunk).map_err(|_| Error::from(format!("{} has no bext chunk", path.display())))?;
+		let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+		if &chunk[0..4] == b"bext" && size >= LOUDNESS_OFFSET + 10 {
+			break;
+		}
+		file.seek(SeekFrom::Current((size + size % 2) as i64)).map_err(map_err)?;
+	}
+
+	let start = file.seek(SeekFrom::Current(0)).map_err(map_err)?;
+	file.seek(SeekFrom::Start(start + VERSION_OFFSET)).map_err(map_err)?;
+	file.write_all(&2u16.to_le_bytes()).map_err(map_err)?;
+
+	file.seek(SeekFrom::Start(start + LOUDNESS_OFFSET)).map_err(map_err)?;
+	let values = [
+		loudness.integrated, loudness.range, loudness.max_true_peak, loudness.max_momentary, loudness.max_short_term
+	];
+	for value in values.iter() {
+		// Stored in hundredths, 0x7fff marks an unknown value
+		let value = match value.is_finite() {
+			true => (value * 100.).round().max(-32768.).min(32767.) as i16,
+			false => 0x7fff
+		};
+		file.write_all(&value.to_le_bytes()).map_err(map_err)?;
+	}
+	Ok(())
+}
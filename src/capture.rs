@@ -0,0 +1,60 @@
+use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::ffmpeg::*;
+use crate::error::Error;
+use crate::options::ReaderOptions;
+
+pub struct Capture {
+	input_ctx: FormatContext,
+	input_stream: Stream,
+	output_ctx: FormatContext,
+	output_stream: Stream,
+	segment_options: Vec<(String, String)>,
+}
+
+impl Capture {
+	// Copies the audio stream of `url` without re-encoding into files named after the strftime `pattern`
+	pub fn open(url: &str, pattern: &str, rotation: Duration, options: ReaderOptions) -> Result<Self, Error> {
+		let mut format_options = Dictionary::from_pairs(&options.format_options)?;
+		let input_ctx = open_read_with(url, options.input_format.as_ref().map(|f| f.as_str()), &mut format_options)?;
+		let input_stream = input_ctx.get_audio_stream()
+			.ok_or("Could not find any audio stream in the input")?;
+
+		let output_ctx = open_write_with(pattern, Some("segment"), &mut Dictionary::new())?;
+		let output_stream = Stream::from_format(&output_ctx)?;
+		output_stream.parameters().copy_from(&input_stream.parameters(), &output_ctx)?;
+
+		let segment_options = vec![
+			("segment_time".to_string(), rotation.as_secs().to_string()),
+			("segment_atclocktime".to_string(), "1".to_string()),
+			("strftime".to_string(), "1".to_string()),
+			("reset_timestamps".to_string(), "1".to_string()),
+		];
+
+		Ok(Capture { input_ctx, input_stream, output_ctx, output_stream, segment_options })
+	}
+
+	// Records until the input ends or `stop` is set, then finalizes the current file
+	pub fn run(&mut self, stop: &AtomicBool) -> Result<(), Error> {
+		self.output_ctx.write_header(&mut Dictionary::from_pairs(&self.segment_options)?)?;
+
+		let mut packet = Packet::empty();
+		while !stop.load(Ordering::Relaxed) {
+			match packet.read(&self.input_ctx) {
+				Err(Error::FFM(FFError::Again)) => continue,
+				Err(Error::FFM(FFError::Eof)) => break,
+				Err(e) => return Err(e),
+				Ok(_) => {}
+			}
+
+			if packet.stream_id() == self.input_stream.index() {
+				packet.rescale_ts(self.input_stream.time_base(), self.output_stream.time_base());
+				packet.set_stream_index(self.output_stream.index());
+				packet.write(&self.output_ctx)?;
+			}
+			packet.reset();
+		}
+
+		self.output_ctx.write_trailer()
+	}
+}
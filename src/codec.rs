@@ -0,0 +1,126 @@
+use crate::sys::*;
+use crate::ffmpeg::*;
+use crate::error::Error;
+use crate::output::negotiate_sample_rate;
+
+// Decodes raw packets of an elementary stream, e.g. Opus packets received over RTP
+pub struct Decoder {
+	codec_ctx: DecoderOpen,
+}
+
+impl Decoder {
+	// `extradata` is the codec specific setup such as the Opus identification header
+	pub fn open(codec: &str, sample_rate: i32, channels: i32, extradata: Option<&[u8]>) -> Result<Self, Error> {
+		let codec_ctx = DecoderOpen::open(DecoderContext::find(codec, sample_rate, channels, extradata)?)?;
+		Ok(Decoder { codec_ctx })
+	}
+
+	// Format of the decoded frames, known for certain once the first frame is decoded
+	pub fn audio_format(&self) -> AudioFormat {
+		self.codec_ctx.ctx().ctx().audio_format()
+	}
+
+	// Frames decoded from one packet, decoders with delay return them with later packets
+	pub fn decode(&mut self, data: &[u8]) -> Result<Vec<Frame>, Error> {
+		Packet::from_data(data)?.send(&self.codec_ctx)?;
+		self.receive_frames()
+	}
+
+	// Returns the delayed frames, the decoder can't be used afterwards
+	pub fn flush(&mut self) -> Result<Vec<Frame>, Error> {
+		Packet::send_flush(&self.codec_ctx)?;
+		self.receive_frames()
+	}
+
+	fn receive_frames(&mut self) -> Result<Vec<Frame>, Error> {
+		let mut frames = Vec::new();
+		loop {
+			let mut frame = Frame::empty().ok_or("Could not allocate frame")?;
+			match frame.recieve(&self.codec_ctx) {
+				Err(Error::FFM(FFError::Again)) | Err(Error::FFM(FFError::Eof)) => return Ok(frames),
+				Err(e) => return Err(e),
+				Ok(_) => frames.push(frame)
+			}
+		}
+	}
+}
+
+// Encodes frames to raw packets of an elementary stream
+pub struct Encoder {
+	codec_ctx: EncoderOpen,
+	pts: i64,
+}
+
+impl Encoder {
+	pub fn open(codec: &str, format: AudioFormat) -> Result<Self, Error> {
+		Self::open_context(EncoderContext::find(codec)?, format)
+	}
+
+	// Encodes in the sample format the codec supports closest to that of `source`, at the nearest supported
+	// sample rate unless `strict_sample_rate`
+	pub fn open_for(codec: &str, source: AudioFormat, strict_sample_rate: bool) -> Result<Self, Error> {
+		let ctx = EncoderContext::find(codec)?;
+		let sample_format = ctx.ctx().codec().formats()
+			.and_then(|formats| pick_best_format(formats, source.sample_format))
+			.ok_or("Could not find appropriate sample format")?;
+		let sample_rate = negotiate_sample_rate(ctx.ctx().codec(), source.sample_rate, strict_sample_rate)?;
+		Self::open_context(ctx, AudioFormat { sample_format, sample_rate, ..source })
+	}
+
+	fn open_context(mut ctx: EncoderContext, format: AudioFormat) -> Result<Self, Error> {
+		match format.is_unspecified() {
+			true => ctx.ctx_mut().set_unspecified_layout(format.channels()),
+			false => ctx.ctx_mut().set_channel_layout(format.channel_layout),
+		}
+		ctx.ctx_mut().set_sample_format(format.sample_format);
+		ctx.ctx_mut().set_sample_rate(format.sample_rate);
+		ctx.ctx_mut().set_time_base(Rational::samples(format.sample_rate));
+		Ok(Encoder { codec_ctx: EncoderOpen::open_standalone(ctx)?, pts: 0 })
+	}
+
+	pub fn audio_format(&self) -> AudioFormat {
+		self.codec_ctx.ctx().ctx().audio_format()
+	}
+
+	// Packets are timestamped in samples
+	pub fn time_base(&self) -> Rational { self.codec_ctx.ctx().ctx().time_base() }
+
+	// Samples per frame every frame but the last has to hold, 0 when any size is accepted
+	pub fn frame_size(&self) -> i32 { self.codec_ctx.ctx().ctx().frame_size() }
+
+	pub(crate) fn context(&self) -> &CodecContext { self.codec_ctx.ctx().ctx() }
+
+	// Describes the encoded stream, e.g. to a muxer
+	pub fn copy_parameters_to(&self, params: &mut CodecParameters) -> Result<(), Error> {
+		unsafe { ffm_op!(avcodec_parameters_from_context(params.as_mut_ptr(), self.codec_ctx.as_mut_ptr())) }
+	}
+
+	// Codec specific setup the decoder needs, e.g. the AAC AudioSpecificConfig
+	pub fn extradata(&self) -> &[u8] { self.codec_ctx.ctx().ctx().extradata() }
+
+	// Frames are timestamped in samples
+	pub fn encode(&mut self, frame: &mut Frame) -> Result<Vec<Packet>, Error> {
+		frame.set_pts(self.pts);
+		self.pts += frame.nb_samples() as i64;
+		frame.send(&mut self.codec_ctx)?;
+		self.receive_packets()
+	}
+
+	// Returns the delayed packets, the encoder can't be used afterwards
+	pub fn flush(&mut self) -> Result<Vec<Packet>, Error> {
+		Frame::send_flush(&mut self.codec_ctx)?;
+		self.receive_packets()
+	}
+
+	fn receive_packets(&mut self) -> Result<Vec<Packet>, Error> {
+		let mut packets = Vec::new();
+		loop {
+			let mut packet = Packet::empty();
+			match packet.recieve(&mut self.codec_ctx) {
+				Err(Error::FFM(FFError::Again)) | Err(Error::FFM(FFError::Eof)) => return Ok(packets),
+				Err(e) => return Err(e),
+				Ok(_) => packets.push(packet)
+			}
+		}
+	}
+}
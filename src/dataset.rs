@@ -0,0 +1,238 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread;
+use litaudio::*;
+use litcontainers::*;
+use crate::error::Error;
+use crate::options::ReaderOptions;
+use crate::read_audio_with;
+
+pub type DecodedItem<T, P, S> = (PathBuf, Result<Container<T, AudioContainer<T, P, S>>, Error>);
+
+pub struct DatasetItem {
+	path: PathBuf,
+}
+
+impl DatasetItem {
+	pub fn path(&self) -> &Path { &self.path }
+
+	pub fn load<T, P, S>(&self, options: ReaderOptions) -> Result<Container<T, AudioContainer<T, P, S>>, Error>
+		where T: Sample, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+	{
+		read_audio_with(&self.path, options)
+	}
+}
+
+pub struct Dataset {
+	paths: Vec<PathBuf>,
+}
+
+impl Dataset {
+	// Recursively collects the files whose name matches a `*`/`?` wildcard pattern, e.g. "*.wav"
+	pub fn scan(dir: &Path, pattern: &str) -> Result<Self, Error> {
+		let mut paths = Vec::new();
+		scan_dir(dir, pattern, &mut paths)?;
+		paths.sort();
+		Ok(Dataset { paths })
+	}
+
+	pub fn from_paths(paths: Vec<PathBuf>) -> Self { Dataset { paths } }
+
+	pub fn len(&self) -> usize { self.paths.len() }
+
+	pub fn is_empty(&self) -> bool { self.paths.is_empty() }
+
+	pub fn paths(&self) -> &[PathBuf] { &self.paths }
+
+	pub fn iter(&self) -> impl Iterator<Item=DatasetItem> + '_ {
+		self.paths.iter().map(|path| DatasetItem { path: path.clone() })
+	}
+
+	// Decodes the files on `workers` threads, yielding them in completion order with their errors captured
+	pub fn decode_parallel<T, P, S>(&self, workers: usize, options: ReaderOptions) -> Receiver<DecodedItem<T, P, S>>
+		where T: Sample + Send + 'static, P: SamplePackingType + Send + 'static,
+		      S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T> + Send + 'static
+	{
+		let workers = workers.max(1);
+		let paths = Arc::new(self.paths.clone());
+		let next = Arc::new(AtomicUsize::new(0));
+		let (sender, receiver) = sync_channel(workers);
+
+		for _ in 0..workers {
+			let paths = paths.clone();
+			let next = next.clone();
+			let sender = sender.clone();
+			let options = options.clone();
+
+			thread::spawn(move || {
+				loop {
+					let i = next.fetch_add(1, Ordering::SeqCst);
+					if i >= paths.len() {
+						break;
+					}
+
+					let result = panic::catch_unwind(AssertUnwindSafe(|| read_audio_with(&paths[i], options.clone())))
+						.unwrap_or_else(|_| Err(Error::from(format!("Decoding {} panicked", paths[i].display()))));
+					if sender.send((paths[i].clone(), result)).is_err() {
+						break;
+					}
+				}
+			});
+		}
+
+		receiver
+	}
+
+	// Decodes up to `lookahead` files ahead of the consumer, yielding them in dataset order
+	pub fn prefetch<T, P, S>(&self, workers: usize, lookahead: usize, options: ReaderOptions) -> Prefetcher<T, P, S>
+		where T: Sample + Send + 'static, P: SamplePackingType + Send + 'static,
+		      S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T> + Send + 'static
+	{
+		Prefetcher::new(self.paths.clone(), workers, lookahead, options)
+	}
+}
+
+pub struct Prefetcher<T, P, S>
+	where T: Sample, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+{
+	shared: Arc<(Mutex<PrefetchState<T, P, S>>, Condvar)>,
+	len: usize,
+}
+
+struct PrefetchState<T, P, S>
+	where T: Sample, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+{
+	// Decoded files by index modulo the lookahead, a slot is free once the consumer took its file
+	slots: Vec<Option<DecodedItem<T, P, S>>>,
+	claimed: usize,
+	consumed: usize,
+	closed: bool,
+}
+
+impl<T, P, S> Prefetcher<T, P, S>
+	where T: Sample + Send + 'static, P: SamplePackingType + Send + 'static,
+	      S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T> + Send + 'static
+{
+	pub fn new(paths: Vec<PathBuf>, workers: usize, lookahead: usize, options: ReaderOptions) -> Self {
+		let workers = workers.max(1);
+		let lookahead = lookahead.max(1);
+		let len = paths.len();
+		let paths = Arc::new(paths);
+		let shared = Arc::new((Mutex::new(PrefetchState {
+			slots: (0..lookahead).map(|_| None).collect(),
+			claimed: 0,
+			consumed: 0,
+			closed: false,
+		}), Condvar::new()));
+
+		for _ in 0..workers.min(lookahead) {
+			let paths = paths.clone();
+			let shared = shared.clone();
+			let options = options.clone();
+
+			thread::spawn(move || {
+				let (state, changed) = &*shared;
+				loop {
+					// Files are only claimed within `lookahead` of the consumer
+					let i = {
+						let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+						while !state.closed && state.claimed < paths.len() && state.claimed >= state.consumed + lookahead {
+							state = changed.wait(state).unwrap_or_else(|e| e.into_inner());
+						}
+						if state.closed || state.claimed >= paths.len() {
+							break;
+						}
+						state.claimed += 1;
+						state.claimed - 1
+					};
+
+					let result = panic::catch_unwind(AssertUnwindSafe(|| read_audio_with(&paths[i], options.clone())))
+						.unwrap_or_else(|_| Err(Error::from(format!("Decoding {} panicked", paths[i].display()))));
+
+					let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+					state.slots[i % lookahead] = Some((paths[i].clone(), result));
+					changed.notify_all();
+				}
+			});
+		}
+
+		Prefetcher { shared, len }
+	}
+}
+
+impl<T, P, S> Iterator for Prefetcher<T, P, S>
+	where T: Sample, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+{
+	type Item = DecodedItem<T, P, S>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let (state, changed) = &*self.shared;
+		let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+		if state.consumed >= self.len {
+			return None;
+		}
+
+		// Workers finish out of order, later files wait in their slot until their turn
+		loop {
+			let slot = state.consumed % state.slots.len();
+			if let Some(item) = state.slots[slot].take() {
+				state.consumed += 1;
+				changed.notify_all();
+				return Some(item);
+			}
+			state = changed.wait(state).unwrap_or_else(|e| e.into_inner());
+		}
+	}
+}
+
+impl<T, P, S> Drop for Prefetcher<T, P, S>
+	where T: Sample, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+{
+	fn drop(&mut self) {
+		let (state, changed) = &*self.shared;
+		state.lock().unwrap_or_else(|e| e.into_inner()).closed = true;
+		changed.notify_all();
+	}
+}
+
+fn scan_dir(dir: &Path, pattern: &str, paths: &mut Vec<PathBuf>) -> Result<(), Error> {
+	let entries = fs::read_dir(dir).map_err(|e| Error::from(format!("Could not read {}: {}", dir.display(), e)))?;
+	for entry in entries {
+		let path = entry.map_err(|e| Error::from(format!("Could not read {}: {}", dir.display(), e)))?.path();
+		if path.is_dir() {
+			scan_dir(&path, pattern, paths)?;
+		} else if path.file_name().and_then(|n| n.to_str()).map_or(false, |n| matches_pattern(n, pattern)) {
+			paths.push(path);
+		}
+	}
+	Ok(())
+}
+
+fn matches_pattern(name: &str, pattern: &str) -> bool {
+	let name: Vec<char> = name.chars().collect();
+	let pattern: Vec<char> = pattern.chars().collect();
+	let (mut n, mut p) = (0, 0);
+	let mut backtrack = None;
+
+	while n < name.len() {
+		if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+			n += 1;
+			p += 1;
+		} else if p < pattern.len() && pattern[p] == '*' {
+			backtrack = Some((p, n));
+			p += 1;
+		} else if let Some((bp, bn)) = backtrack {
+			p = bp + 1;
+			n = bn + 1;
+			backtrack = Some((bp, bn + 1));
+		} else {
+			return false;
+		}
+	}
+
+	pattern[p..].iter().all(|c| *c == '*')
+}
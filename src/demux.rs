@@ -0,0 +1,51 @@
+use crate::ffmpeg::*;
+use crate::error::Error;
+use crate::options::ReaderOptions;
+
+// Reads the compressed packets of every stream in file order without decoding them
+pub struct Demuxer {
+	format_ctx: FormatContext,
+	streams: Vec<Stream>,
+}
+
+impl Demuxer {
+	pub fn open(path: &str) -> Result<Self, Error> {
+		Self::open_with(path, &ReaderOptions::new())
+	}
+
+	// Uses the input format and format options of `options`
+	pub fn open_with(path: &str, options: &ReaderOptions) -> Result<Self, Error> {
+		let mut format_options = Dictionary::from_pairs(&options.format_options)?;
+		let format_ctx = open_read_with(path, options.input_format.as_ref().map(|f| f.as_str()), &mut format_options)?;
+		let streams = format_ctx.streams();
+		Ok(Demuxer { format_ctx, streams })
+	}
+
+	pub fn format_ctx(&self) -> &FormatContext { &self.format_ctx }
+
+	// Indexed by the stream index of the packets
+	pub fn streams(&self) -> &[Stream] { &self.streams }
+
+	// The packet timestamps are in the time base of its stream
+	pub fn stream(&self, packet: &Packet) -> Option<&Stream> {
+		self.streams.get(packet.stream_id() as usize)
+	}
+
+	// None at the end of the input
+	pub fn read_packet(&mut self) -> Result<Option<Packet>, Error> {
+		let mut packet = Packet::empty();
+		match packet.read(&self.format_ctx) {
+			Err(Error::FFM(FFError::Eof)) => Ok(None),
+			Err(e) => Err(e),
+			Ok(_) => Ok(Some(packet))
+		}
+	}
+}
+
+impl Iterator for Demuxer {
+	type Item = Result<Packet, Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.read_packet().transpose()
+	}
+}
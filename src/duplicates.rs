@@ -0,0 +1,54 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use litaudio::*;
+use litcontainers::*;
+use num_traits::ToPrimitive;
+use crate::error::Error;
+use crate::options::ReaderOptions;
+use crate::reader::Reader;
+use crate::dataset::Dataset;
+use crate::assets::{fnv1a_extend, FNV_OFFSET};
+use crate::samples::*;
+
+// Recordings are decoded to this format before hashing, so containers, tags and source rates don't matter
+const HASH_SAMPLE_RATE: i32 = 44100;
+const HASH_CHANNELS: usize = 2;
+const BLOCK_SIZE: usize = 4096;
+
+// FNV-1a of the decoded samples as interleaved 16 bit little endian PCM at 44.1 kHz stereo. Equal for
+// lossless copies of a recording in different containers, lossy encodes of it still hash differently
+pub fn pcm_hash<T, P, S>(path: &Path) -> Result<u64, Error>
+	where T: Sample + ToPrimitive, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+{
+	let path = path.to_str().ok_or_else(|| Error::from(format!("Invalid path: {}", path.display())))?;
+	let reader = Reader::<T, P, S>::open_with(
+		path,
+		ReaderOptions::new().channel_count(Some(HASH_CHANNELS)).target_sample_rate(Some(HASH_SAMPLE_RATE))
+	)?;
+
+	let mut hash = FNV_OFFSET;
+	let mut bytes = Vec::with_capacity(BLOCK_SIZE * HASH_CHANNELS * 2);
+	reader.for_each_block(BLOCK_SIZE, |_, block| {
+		let channels: Vec<Vec<T>> = (0..block.channels()).map(|c| channel_iter(block, c).collect()).collect();
+		bytes.clear();
+		for i in 0..block.samples() {
+			for channel in &channels {
+				let v = (to_normalized(channel[i]).max(-1.).min(1.) * 32767.).round() as i16;
+				bytes.extend_from_slice(&v.to_le_bytes());
+			}
+		}
+		hash = fnv1a_extend(hash, &bytes);
+	})?;
+	Ok(hash)
+}
+
+// Groups the files of the dataset with equal PCM hashes, files failing to decode abort the scan
+pub fn find_duplicates<T, P, S>(dataset: &Dataset) -> Result<Vec<Vec<PathBuf>>, Error>
+	where T: Sample + ToPrimitive, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+{
+	let mut groups: BTreeMap<u64, Vec<PathBuf>> = BTreeMap::new();
+	for path in dataset.paths() {
+		groups.entry(pcm_hash::<T, P, S>(path)?).or_default().push(path.clone());
+	}
+	Ok(groups.into_iter().map(|(_, paths)| paths).filter(|paths| paths.len() > 1).collect())
+}
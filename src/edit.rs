@@ -0,0 +1,84 @@
+use std::ops::Range;
+use litaudio::*;
+use litcontainers::*;
+use num_traits::{ToPrimitive, NumCast};
+use crate::error::Error;
+use crate::samples::*;
+
+// A source placed on the output timeline at `offset` samples, scaled by `gain_db`
+pub struct Overlay<'a, S> {
+	pub audio: &'a S,
+	pub offset: usize,
+	pub gain_db: f64,
+}
+
+impl<'a, S> Overlay<'a, S> {
+	pub fn new(audio: &'a S, offset: usize, gain_db: f64) -> Self { Overlay { audio, offset, gain_db } }
+}
+
+// Sums the sources into a container spanning all of them, the result is clipped to the sample range
+pub fn mix<T, P, S, SD>(sources: &[Overlay<S>]) -> Result<AudioContainer<T, P, SD>, Error>
+	where T: Sample + ToPrimitive + NumCast, P: SamplePackingType, S: AudioStorage<T, P>,
+	      SD: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+{
+	let (channels, sample_rate) = common_format(sources.iter().map(|s| s.audio))?;
+	let length = sources.iter().map(|s| s.offset + s.audio.samples()).max().unwrap_or(0);
+
+	let mut output = AudioContainer::zeros(Size::new(SD::Rows::from_usize(channels), D!(length)));
+	output.set_sample_rate(sample_rate);
+
+	let mut sum = vec![0f64; length];
+	for c in 0..channels {
+		sum.iter_mut().for_each(|v| *v = 0.);
+		for source in sources {
+			let gain = 10f64.powf(source.gain_db / 20.);
+			for (i, v) in channel_iter(source.audio, c).enumerate() {
+				sum[source.offset + i] += to_normalized(v) * gain;
+			}
+		}
+		map_channel(&mut output, c, |i, _| from_normalized(sum[i].max(-1.).min(1.)));
+	}
+
+	Ok(output)
+}
+
+// Concatenates sample ranges of the sources
+pub fn splice<T, P, S, SD>(ranges: &[(&S, Range<usize>)]) -> Result<AudioContainer<T, P, SD>, Error>
+	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P>,
+	      SD: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+{
+	let (channels, sample_rate) = common_format(ranges.iter().map(|(s, _)| *s))?;
+	for (audio, range) in ranges {
+		if range.start > range.end || range.end > audio.samples() {
+			return Err(Error::from(format!("Range {:?} is out of bounds of {} samples", range, audio.samples())));
+		}
+	}
+	let length = ranges.iter().map(|(_, r)| r.len()).sum();
+
+	let mut output = AudioContainer::zeros(Size::new(SD::Rows::from_usize(channels), D!(length)));
+	output.set_sample_rate(sample_rate);
+
+	let mut offset = 0;
+	for (audio, range) in ranges {
+		copy_samples(*audio, range.start, &mut output, offset, range.len());
+		offset += range.len();
+	}
+
+	Ok(output)
+}
+
+fn common_format<'a, T, P, S, I>(mut sources: I) -> Result<(usize, i32), Error>
+	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P> + 'a, I: Iterator<Item=&'a S>
+{
+	let first = sources.next().ok_or("Nothing to assemble")?;
+	let format = (first.channels(), first.sample_rate());
+	for audio in sources {
+		if (audio.channels(), audio.sample_rate()) != format {
+			return Err(Error::from(format!(
+				"Sources differ in format: {} channels at {} Hz and {} channels at {} Hz",
+				format.0, format.1, audio.channels(), audio.sample_rate()
+			)));
+		}
+	}
+	Ok(format)
+}
@@ -8,6 +8,7 @@ use crate::ffmpeg::FFError;
 #[derive(Debug)]
 pub enum Error {
 	FFM(ffmpeg::utils::FFError),
+	Unsupported(String),
 	CustomError(String)
 }
 
@@ -27,6 +28,7 @@ impl std::error::Error for Error {
 	fn description(&self) -> &str {
 		match self {
 			Error::FFM(e) => e.description(),
+			Error::Unsupported(e) => &e,
 			Error::CustomError(e) => &e
 		}
 	}
@@ -0,0 +1,58 @@
+use std::ptr;
+use std::ffi::CString;
+use crate::sys::*;
+use crate::error::Error;
+use super::codec::*;
+use super::containers::*;
+use super::utils::*;
+use super::rational::*;
+
+// Rewrites packets without decoding, e.g. `aac_adtstoasc` for ADTS to MP4
+pub struct BitstreamFilter {
+	ptr: *mut AVBSFContext,
+}
+
+impl BitstreamFilter {
+	pub fn new(name: &str, params: &CodecParameters, time_base: Rational) -> Result<Self, Error> {
+		let cname = CString::new(name).map_err(|_| Error::from(format!("Invalid bitstream filter name: {}", name)))?;
+		unsafe {
+			let filter = av_bsf_get_by_name(cname.as_ptr());
+			if filter.is_null() {
+				return Err(Error::Unsupported(format!("No {} bitstream filter is available in the linked FFmpeg build.", name)));
+			}
+
+			let mut bsf = BitstreamFilter { ptr: ptr::null_mut() };
+			ffm_op!(av_bsf_alloc(filter, &mut bsf.ptr))?;
+			ffm_ret!(avcodec_parameters_copy((*bsf.ptr).par_in, params.as_ptr()))?;
+			(*bsf.ptr).time_base_in = time_base.into();
+			ffm_op!(av_bsf_init(bsf.ptr))?;
+			Ok(bsf)
+		}
+	}
+
+	// Parameters of the filtered stream, such as the extradata derived from the first packets
+	pub fn output_parameters(&self) -> CodecParameters {
+		CodecParameters::new(unsafe { (*self.ptr).par_out }).unwrap()
+	}
+
+	pub fn output_time_base(&self) -> Rational { Rational::from(unsafe { (*self.ptr).time_base_out }) }
+
+	// Takes over the data of `packet`, leaving it empty
+	pub fn send(&mut self, packet: &mut Packet) -> Result<(), Error> {
+		unsafe { ffm_op!(av_bsf_send_packet(self.ptr, packet.as_mut_ptr())) }
+	}
+
+	pub fn send_flush(&mut self) -> Result<(), Error> {
+		unsafe { ffm_op!(av_bsf_send_packet(self.ptr, ptr::null_mut())) }
+	}
+
+	pub fn receive(&mut self, packet: &mut Packet) -> Result<(), Error> {
+		unsafe { ffm_op!(av_bsf_receive_packet(self.ptr, packet.as_mut_ptr())) }
+	}
+}
+
+impl Drop for BitstreamFilter {
+	fn drop(&mut self) {
+		unsafe { av_bsf_free(&mut self.ptr); }
+	}
+}
@@ -1,3 +1,5 @@
+use std::ffi::CStr;
+use std::str::from_utf8_unchecked;
 use sys::*;
 use crate::ffmpeg::utils::*;
 use crate::ffmpeg::format::*;
@@ -6,6 +8,18 @@ ff_wrap_struct!(Codec, AVCodec);
 ff_wrap!(Codec, AVCodec);
 
 impl Codec {
+	// The preferred decoder of the linked FFmpeg build for `id`
+	pub fn decoder(id: AVCodecID) -> Option<Self> {
+		Codec::new(unsafe { avcodec_find_decoder(id) })
+	}
+
+	pub fn id(&self) -> AVCodecID { self.as_ref().id }
+
+	// Implementation name such as "libspeex", which may differ from the codec name
+	pub fn name(&self) -> &'static str {
+		unsafe { from_utf8_unchecked(CStr::from_ptr(self.as_ref().name).to_bytes()) }
+	}
+
 	pub fn formats(&self) -> Option<FormatIter> {
 		if self.as_ref().sample_fmts.is_null() {
 			None
@@ -13,4 +27,25 @@ impl Codec {
 			Some(FormatIter::new(self.as_ref().sample_fmts))
 		}
 	}
+
+	// Whether the encoder takes a last frame shorter than its frame size, or frames of any size
+	pub fn accepts_short_frames(&self) -> bool {
+		self.as_ref().capabilities as u32 & (AV_CODEC_CAP_SMALL_LAST_FRAME | AV_CODEC_CAP_VARIABLE_FRAME_SIZE) != 0
+	}
+
+	// Rates the encoder accepts, `None` if it takes any
+	pub fn sample_rates(&self) -> Option<Vec<i32>> {
+		let mut ptr = self.as_ref().supported_samplerates;
+		if ptr.is_null() {
+			return None;
+		}
+		let mut sample_rates = Vec::new();
+		unsafe {
+			while *ptr != 0 {
+				sample_rates.push(*ptr);
+				ptr = ptr.add(1);
+			}
+		}
+		Some(sample_rates)
+	}
 }
@@ -1,6 +1,11 @@
+use std::ptr;
+use std::slice;
+use libc::c_void;
 use sys::*;
+use crate::error::Error;
 use crate::ffmpeg::utils::*;
 use crate::ffmpeg::format::*;
+use crate::ffmpeg::rational::*;
 use super::codec::*;
 
 pub struct CodecContext {
@@ -24,9 +29,21 @@ impl CodecContext {
 	}
 
 	pub fn channel_layout(&self) -> ChannelLayout {
+		let channel_layout = ChannelLayout::from_bits_truncate(self.as_ref().channel_layout);
+		// Decoders downmixing on request update the channel count before the layout
+		match channel_layout.channels() == self.as_ref().channels {
+			true => channel_layout.without_downmix(),
+			false => ChannelLayout::default(self.as_ref().channels)
+		}
+	}
+
+	pub fn channels(&self) -> i32 { self.as_ref().channels }
+
+	// Channels without speaker positions, such as ambisonic components, keep an unspecified layout
+	pub fn audio_format(&self) -> AudioFormat {
 		match self.as_ref().channel_layout {
-			0 => ChannelLayout::default(self.as_ref().channels),
-			_ => ChannelLayout::from_bits_truncate(self.as_ref().channel_layout)
+			0 => AudioFormat::unspecified(self.channels(), self.sample_format(), self.sample_rate()),
+			_ => AudioFormat::new(self.channel_layout(), self.sample_format(), self.sample_rate())
 		}
 	}
 
@@ -35,6 +52,12 @@ impl CodecContext {
 		self.as_mut_ref().channel_layout = channel_layout.bits();
 	}
 
+	// Leaves the channel positions untagged, e.g. for ambisonic components
+	pub fn set_unspecified_layout(&mut self, channels: i32) {
+		self.as_mut_ref().channels = channels;
+		self.as_mut_ref().channel_layout = 0;
+	}
+
 	pub fn sample_rate(&self) -> i32 {
 		self.as_ref().sample_rate
 	}
@@ -51,6 +74,45 @@ impl CodecContext {
 		self.as_mut_ref().sample_fmt = sample_format.into();
 	}
 
+	pub fn time_base(&self) -> Rational { Rational::from(self.as_ref().time_base) }
+
+	pub fn set_time_base(&mut self, time_base: Rational) {
+		self.as_mut_ref().time_base = time_base.into();
+	}
+
+	pub fn set_compression_level(&mut self, compression_level: i32) {
+		self.as_mut_ref().compression_level = compression_level;
+	}
+
+	// Variable bitrate quality on the codec's own scale, e.g. -1 to 10 for Vorbis
+	pub fn set_quality(&mut self, quality: f32) {
+		self.as_mut_ref().flags |= AV_CODEC_FLAG_QSCALE as i32;
+		self.as_mut_ref().global_quality = (quality * FF_QP2LAMBDA as f32) as i32;
+	}
+
+	pub fn extradata(&self) -> &[u8] {
+		match self.as_ref().extradata.is_null() {
+			true => &[],
+			false => unsafe { slice::from_raw_parts(self.as_ref().extradata, self.as_ref().extradata_size as usize) }
+		}
+	}
+
+	// Copied into a padded buffer owned by the context
+	pub fn set_extradata(&mut self, extradata: &[u8]) -> Result<(), Error> {
+		unsafe {
+			let size = extradata.len() + AV_INPUT_BUFFER_PADDING_SIZE as usize;
+			let buf = av_mallocz(size) as *mut u8;
+			if buf.is_null() {
+				return Err(Error::from("Could not allocate extradata"));
+			}
+			ptr::copy_nonoverlapping(extradata.as_ptr(), buf, extradata.len());
+			av_freep(&mut self.as_mut_ref().extradata as *mut *mut u8 as *mut c_void);
+			self.as_mut_ref().extradata = buf;
+			self.as_mut_ref().extradata_size = extradata.len() as i32;
+		}
+		Ok(())
+	}
+
 	pub fn frame_size(&self) -> i32 { self.as_ref().frame_size }
 }
 
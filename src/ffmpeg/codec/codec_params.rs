@@ -1,5 +1,136 @@
+use std::ffi::CStr;
+use std::ptr;
+use std::slice;
+use std::str::from_utf8_unchecked;
+use libc::c_void;
 use crate::sys::*;
+use crate::error::Error;
 use crate::ffmpeg::utils::*;
+use crate::ffmpeg::format_context::FormatContext;
 
 ff_wrap_struct!(CodecParameters, AVCodecParameters);
-ff_wrap!(CodecParameters, AVCodecParameters);
\ No newline at end of file
+ff_wrap!(CodecParameters, AVCodecParameters);
+
+impl CodecParameters {
+	// The codec tag is only kept when `format_ctx` maps the codec to the same tag, otherwise its muxer picks one
+	pub fn copy_from(&mut self, other: &CodecParameters, format_ctx: &FormatContext) -> Result<(), Error> {
+		unsafe {
+			ffm_ret!(avcodec_parameters_copy(self.as_mut_ptr(), other.as_ptr()))?;
+			let tags = (*format_ctx.get_output_format()).codec_tag;
+			if av_codec_get_tag(tags, other.codec_id()) != other.as_ref().codec_tag {
+				self.as_mut_ref().codec_tag = 0;
+			}
+		}
+		Ok(())
+	}
+
+	pub fn codec_id(&self) -> AVCodecID { self.as_ref().codec_id }
+
+	pub fn is_audio(&self) -> bool { self.as_ref().codec_type == AVMediaType::AVMEDIA_TYPE_AUDIO }
+
+	// Files such as fonts or cover images stored in the container, e.g. Matroska attachments
+	pub fn is_attachment(&self) -> bool { self.as_ref().codec_type == AVMediaType::AVMEDIA_TYPE_ATTACHMENT }
+
+	pub fn extradata(&self) -> &[u8] {
+		match self.as_ref().extradata.is_null() {
+			true => &[],
+			false => unsafe { slice::from_raw_parts(self.as_ref().extradata, self.as_ref().extradata_size as usize) }
+		}
+	}
+
+	// Copied into a padded buffer owned by the parameters
+	pub fn set_extradata(&mut self, extradata: &[u8]) -> Result<(), Error> {
+		unsafe {
+			let size = extradata.len() + AV_INPUT_BUFFER_PADDING_SIZE as usize;
+			let buf = av_mallocz(size) as *mut u8;
+			if buf.is_null() {
+				return Err(Error::from("Could not allocate extradata"));
+			}
+			ptr::copy_nonoverlapping(extradata.as_ptr(), buf, extradata.len());
+			av_freep(&mut self.as_mut_ref().extradata as *mut *mut u8 as *mut c_void);
+			self.as_mut_ref().extradata = buf;
+			self.as_mut_ref().extradata_size = extradata.len() as i32;
+		}
+		Ok(())
+	}
+
+	pub fn codec_name(&self) -> &'static str {
+		unsafe { from_utf8_unchecked(CStr::from_ptr(avcodec_get_name(self.codec_id())).to_bytes()) }
+	}
+
+	// E.g. "LC" for AAC, None when the codec has no profiles or it is unknown
+	pub fn profile_name(&self) -> Option<&'static str> {
+		unsafe {
+			let name = avcodec_profile_name(self.codec_id(), self.as_ref().profile);
+			match name.is_null() {
+				true => None,
+				false => Some(from_utf8_unchecked(CStr::from_ptr(name).to_bytes()))
+			}
+		}
+	}
+
+	pub fn profile(&self) -> Option<i32> {
+		match self.as_ref().profile {
+			FF_PROFILE_UNKNOWN => None,
+			profile => Some(profile)
+		}
+	}
+
+	pub fn level(&self) -> Option<i32> {
+		match self.as_ref().level {
+			FF_LEVEL_UNKNOWN => None,
+			level => Some(level)
+		}
+	}
+
+	// None for variable or unknown bit rates
+	pub fn bit_rate(&self) -> Option<i64> {
+		match self.as_ref().bit_rate {
+			0 => None,
+			bit_rate => Some(bit_rate)
+		}
+	}
+
+	// Precision of the coded samples, e.g. 24 for 24 bit FLAC decoded to s32
+	pub fn bits_per_sample(&self) -> Option<i32> {
+		match (self.as_ref().bits_per_raw_sample, self.as_ref().bits_per_coded_sample) {
+			(0, 0) => None,
+			(0, bits) | (bits, _) => Some(bits)
+		}
+	}
+
+	// Whether the codec only supports lossless compression
+	pub fn is_lossless(&self) -> bool {
+		unsafe {
+			let descriptor = avcodec_descriptor_get(self.codec_id());
+			!descriptor.is_null() && (*descriptor).props & AV_CODEC_PROP_LOSSLESS as i32 != 0
+				&& (*descriptor).props & AV_CODEC_PROP_LOSSY as i32 == 0
+		}
+	}
+
+	pub fn sample_rate(&self) -> i32 { self.as_ref().sample_rate }
+
+	pub fn channels(&self) -> i32 { self.as_ref().channels }
+
+	// Samples per packet for codecs with a fixed frame size, 0 otherwise
+	pub fn frame_size(&self) -> i32 { self.as_ref().frame_size }
+
+	pub fn is_dolby(&self) -> bool {
+		match self.codec_id() {
+			AVCodecID::AV_CODEC_ID_AC3 | AVCodecID::AV_CODEC_ID_EAC3 => true,
+			_ => false
+		}
+	}
+
+	pub fn is_dts(&self) -> bool { self.codec_id() == AVCodecID::AV_CODEC_ID_DTS }
+
+	pub fn is_s302m(&self) -> bool { self.codec_id() == AVCodecID::AV_CODEC_ID_S302M }
+
+	pub fn is_dsd(&self) -> bool {
+		match self.codec_id() {
+			AVCodecID::AV_CODEC_ID_DSD_LSBF | AVCodecID::AV_CODEC_ID_DSD_MSBF
+			| AVCodecID::AV_CODEC_ID_DSD_LSBF_PLANAR | AVCodecID::AV_CODEC_ID_DSD_MSBF_PLANAR => true,
+			_ => false
+		}
+	}
+}
@@ -5,7 +5,9 @@ use super::codec_context::*;
 use super::codec_params::*;
 use super::super::utils::*;
 use super::super::format::*;
+use super::super::dictionary::*;
 use std::ptr;
+use std::ffi::CString;
 
 pub struct DecoderContext {
 	ctx: CodecContext
@@ -14,13 +16,43 @@ pub struct DecoderContext {
 wrap_ff_wrap!(DecoderContext, CodecContext, AVCodecContext, ctx, ctx_mut);
 
 impl DecoderContext {
-	pub fn create(params: &CodecParameters) -> Result<Self, Error> {
-		let codec_ptr = unsafe { avcodec_find_decoder(params.as_ref().codec_id) };
-		let codec = Codec::new(codec_ptr).ok_or("Can't find fitting decoder.")?;
+	pub fn create(params: &CodecParameters, codec_name: Option<&str>) -> Result<Self, Error> {
+		let codec_ptr = match codec_name {
+			None => unsafe { avcodec_find_decoder(params.codec_id()) },
+			Some(name) => {
+				let cname = CString::new(name).map_err(|_| Error::from(format!("Invalid codec name: {}", name)))?;
+				unsafe { avcodec_find_decoder_by_name(cname.as_ptr()) }
+			}
+		};
+		let codec = Codec::new(codec_ptr).ok_or_else(|| Error::Unsupported(format!(
+			"No {} decoder is available in the linked FFmpeg build.", codec_name.unwrap_or(params.codec_name())
+		)))?;
+		if codec.id() != params.codec_id() {
+			return Err(Error::Unsupported(format!(
+				"The {} decoder can't decode {} streams.", codec_name.unwrap_or(""), params.codec_name()
+			)));
+		}
 
+		// Owned before the parameters are applied, so failing to apply them frees the context
 		let ptr = unsafe { avcodec_alloc_context3(codec.as_ptr()) };
-		unsafe { ffm_op!(avcodec_parameters_to_context(ptr, params.as_ptr()))? };
 		let ctx = CodecContext::new(ptr, codec).ok_or("Can't allocate a codec context.")?;
+		unsafe { ffm_op!(avcodec_parameters_to_context(ctx.as_mut_ptr(), params.as_ptr()))? };
+		Ok(DecoderContext { ctx })
+	}
+
+	// Decoder for raw packets without container parameters, `extradata` is the codec specific setup
+	pub fn find(codec_name: &str, sample_rate: i32, channels: i32, extradata: Option<&[u8]>) -> Result<Self, Error> {
+		let cname = CString::new(codec_name).map_err(|_| Error::from(format!("Invalid codec name: {}", codec_name)))?;
+		let codec = Codec::new(unsafe { avcodec_find_decoder_by_name(cname.as_ptr()) })
+			.ok_or_else(|| Error::Unsupported(format!("No {} decoder is available in the linked FFmpeg build.", codec_name)))?;
+
+		let ptr = unsafe { avcodec_alloc_context3(codec.as_ptr()) };
+		let mut ctx = CodecContext::new(ptr, codec).ok_or("Can't allocate a codec context.")?;
+		ctx.set_sample_rate(sample_rate);
+		ctx.set_unspecified_layout(channels);
+		if let Some(extradata) = extradata {
+			ctx.set_extradata(extradata)?;
+		}
 		Ok(DecoderContext { ctx })
 	}
 
@@ -28,6 +60,10 @@ impl DecoderContext {
 		self.as_mut_ref().request_sample_fmt = sample_format.into();
 	}
 
+	pub fn set_request_channel_layout(&mut self, channel_layout: ChannelLayout) {
+		self.as_mut_ref().request_channel_layout = channel_layout.bits();
+	}
+
 	pub fn sample_fmt(&self) -> SampleFormat { SampleFormat::from(self.as_ref().request_sample_fmt) }
 }
 
@@ -40,13 +76,23 @@ wrap_ff_wrap!(DecoderOpen, DecoderContext, AVCodecContext, ctx, ctx_mut);
 
 impl DecoderOpen {
 	pub fn open(ctx: DecoderContext) -> Result<DecoderOpen, Error> {
+		Self::open_with(ctx, &mut Dictionary::new())
+	}
+
+	pub fn open_with(ctx: DecoderContext, options: &mut Dictionary) -> Result<DecoderOpen, Error> {
 		unsafe {
-			ffm_op!(avcodec_open2(ctx.as_mut_ptr(), ctx.ctx().codec().as_ptr(), ptr::null_mut()))?;
+			ffm_op!(avcodec_open2(ctx.as_mut_ptr(), ctx.ctx().codec().as_ptr(), options.as_mut_ptr_ref()))?;
 		}
 		Ok(DecoderOpen { ctx })
 	}
 }
 
+impl DecoderOpen {
+	pub fn flush(&self) {
+		unsafe { avcodec_flush_buffers(self.as_mut_ptr()) }
+	}
+}
+
 impl Drop for DecoderOpen {
 	fn drop(&mut self) {
 		unsafe {
@@ -6,16 +6,34 @@ use super::codec_context::*;
 use super::codec::*;
 use super::super::stream::*;
 use std::ptr;
+use std::ffi::CString;
 
 pub struct EncoderContext {
 	ctx: CodecContext
 }
 
 impl EncoderContext {
-	pub fn create(format_ctx: &FormatContext) -> Result<Self, Error> {
-		let codec_ptr = unsafe { avcodec_find_encoder((*format_ctx.get_output_format()).audio_codec) };
+	pub fn create(format_ctx: &FormatContext, codec_name: Option<&str>) -> Result<Self, Error> {
+		let codec_ptr = match codec_name {
+			None => unsafe { avcodec_find_encoder((*format_ctx.get_output_format()).audio_codec) },
+			Some(name) => {
+				let cname = CString::new(name).map_err(|_| Error::from(format!("Invalid codec name: {}", name)))?;
+				unsafe { avcodec_find_encoder_by_name(cname.as_ptr()) }
+			}
+		};
 		let codec = Codec::new(codec_ptr).ok_or(Error::from("Can't find fitting encoder."))?;
+		Self::from_codec(codec)
+	}
+
+	// Encoder by name without an output container
+	pub fn find(codec_name: &str) -> Result<Self, Error> {
+		let cname = CString::new(codec_name).map_err(|_| Error::from(format!("Invalid codec name: {}", codec_name)))?;
+		let codec = Codec::new(unsafe { avcodec_find_encoder_by_name(cname.as_ptr()) })
+			.ok_or_else(|| Error::Unsupported(format!("No {} encoder is available in the linked FFmpeg build.", codec_name)))?;
+		Self::from_codec(codec)
+	}
 
+	fn from_codec(codec: Codec) -> Result<Self, Error> {
 		let ptr = unsafe { avcodec_alloc_context3(codec.as_ptr()) };
 		let ctx = CodecContext::new(ptr, codec).ok_or(Error::from("Can't allocate a codec context."))?;
 		Ok(EncoderContext { ctx })
@@ -33,9 +51,15 @@ wrap_ff_wrap!(EncoderOpen, EncoderContext, AVCodecContext, ctx, ctx_mut);
 
 impl EncoderOpen {
 	pub fn open(ctx: EncoderContext, stream: &mut Stream) -> Result<EncoderOpen, Error> {
+		let ctx = Self::open_standalone(ctx)?;
+		unsafe { ffm_op!(avcodec_parameters_from_context(stream.parameters().as_mut_ptr(), ctx.as_mut_ptr()))?; }
+		Ok(ctx)
+	}
+
+	// Opens the encoder without an output stream to describe
+	pub fn open_standalone(ctx: EncoderContext) -> Result<EncoderOpen, Error> {
 		unsafe {
 			ffm_op!(avcodec_open2(ctx.as_mut_ptr(), ctx.ctx().codec().as_ptr(), ptr::null_mut()))?;
-			ffm_op!(avcodec_parameters_from_context(stream.parameters().as_mut_ptr(), ctx.as_mut_ptr()))?;
 		}
 		Ok(EncoderOpen { ctx })
 	}
@@ -0,0 +1,53 @@
+use libc::c_void;
+use crate::sys::*;
+use crate::error::Error;
+use super::frame::*;
+use super::super::format::*;
+
+// Buffers samples pushed in any count, so encoders with a fixed frame size get exactly that many per frame
+pub struct AudioFifo {
+	ptr: *mut AVAudioFifo,
+}
+
+impl AudioFifo {
+	// Grows beyond `capacity` samples when needed
+	pub fn new(sample_format: SampleFormat, channels: i32, capacity: i32) -> Result<Self, Error> {
+		let ptr = unsafe { av_audio_fifo_alloc(sample_format.into(), channels, capacity.max(1)) };
+		if ptr.is_null() {
+			return Err(Error::from("Could not allocate the audio fifo"));
+		}
+		Ok(AudioFifo { ptr })
+	}
+
+	// Buffered samples per channel
+	pub fn size(&self) -> i32 { unsafe { av_audio_fifo_size(self.ptr) } }
+
+	pub fn is_empty(&self) -> bool { self.size() == 0 }
+
+	// Appends `count` samples from `data`, one pointer per plane
+	pub unsafe fn write(&mut self, data: *const *const u8, count: i32) -> Result<i32, Error> {
+		ffm_ret!(av_audio_fifo_write(self.ptr, data as *mut *mut c_void, count))
+	}
+
+	// Appends all samples of the frame, which has to be in the format of the fifo
+	pub fn write_frame(&mut self, frame: &Frame) -> Result<i32, Error> {
+		unsafe { self.write((*frame.as_ptr()).extended_data as *const *const u8, frame.nb_samples()) }
+	}
+
+	// Moves up to `frame.nb_samples()` samples into the frame, returns how many were moved
+	pub fn read_frame(&mut self, frame: &mut Frame) -> Result<i32, Error> {
+		unsafe {
+			ffm_ret!(av_audio_fifo_read(self.ptr, (*frame.as_mut_ptr()).extended_data as *mut *mut c_void, frame.nb_samples()))
+		}
+	}
+
+	pub fn reset(&mut self) {
+		unsafe { av_audio_fifo_reset(self.ptr) }
+	}
+}
+
+impl Drop for AudioFifo {
+	fn drop(&mut self) {
+		unsafe { av_audio_fifo_free(self.ptr) }
+	}
+}
@@ -1,10 +1,13 @@
 use std::mem;
 use std::ptr;
+use std::slice;
+use litaudio::Sample;
 use crate::sys::*;
 use crate::error::Error;
 use super::super::codec::*;
 use super::super::format::*;
 use super::super::utils::*;
+use super::super::dictionary::*;
 
 ff_wrap_struct!(Frame, AVFrame);
 ff_wrap!(Frame, AVFrame);
@@ -14,6 +17,20 @@ impl Frame {
 		Frame::new(unsafe {av_frame_alloc()})
 	}
 
+	// Frame with buffers for `nb_samples` samples of `format`
+	pub fn alloc(format: AudioFormat, nb_samples: i32) -> Result<Self, Error> {
+		let mut frame = Frame::empty().ok_or("Could not allocate frame")?;
+		frame.set_channel_layout(format.channel_layout);
+		frame.as_mut_ref().channels = format.channels();
+		frame.set_sample_format(format.sample_format);
+		frame.set_sample_rate(format.sample_rate);
+		frame.set_nb_samples(nb_samples);
+
+		unsafe { ffm_op!(av_frame_get_buffer(frame.as_mut_ptr(), 0))? };
+
+		Ok(frame)
+	}
+
 	pub fn send(&mut self, ctx: &mut EncoderOpen) -> Result<i32, Error> {
 		unsafe {
 			ffm_ret!(avcodec_send_frame(ctx.as_mut_ptr(), self.as_ptr()))
@@ -28,29 +45,125 @@ impl Frame {
 
 	pub fn recieve(&mut self, ctx: &DecoderOpen) -> Result<(), Error> {
 		unsafe {
-			ffm_op!(avcodec_receive_frame(ctx.as_mut_ptr(), self.ptr))
+			ffm_op!(avcodec_receive_frame(ctx.as_mut_ptr(), self.ptr))?;
+		}
+		// Tagged like the decoder context, so the frames match the format reported at open
+		let channel_layout = self.channel_layout();
+		if channel_layout.bits() == self.as_ref().channel_layout {
+			self.set_channel_layout(channel_layout.without_downmix());
 		}
+		Ok(())
 	}
 
+	pub fn reset(&mut self) {
+		unsafe { av_frame_unref(self.ptr); }
+	}
+
+	pub fn make_writable(&mut self) -> Result<(), Error> {
+		unsafe { ffm_op!(av_frame_make_writable(self.ptr)) }
+	}
+
+	pub fn pts(&self) -> i64 { self.as_ref().pts }
+
+	pub fn set_pts(&mut self, pts: i64) {
+		self.as_mut_ref().pts = pts;
+	}
+
+	// Timestamp estimated by the decoder, falls back to the packet timestamps when pts is missing
+	pub fn best_effort_timestamp(&self) -> i64 { self.as_ref().best_effort_timestamp }
+
+	pub fn duration(&self) -> i64 { self.as_ref().pkt_duration }
+
 	pub fn nb_samples(&self) -> i32 { self.as_ref().nb_samples }
 
 	pub fn set_nb_samples(&mut self, nb_samples: i32) {
 		self.as_mut_ref().nb_samples = nb_samples;
 	}
 
+	pub fn channel_layout(&self) -> ChannelLayout {
+		let channel_layout = ChannelLayout::from_bits_truncate(self.as_ref().channel_layout);
+		match channel_layout.channels() == self.as_ref().channels {
+			true => channel_layout,
+			false => ChannelLayout::default(self.as_ref().channels)
+		}
+	}
+
 	pub fn set_channel_layout(&mut self, channel_layout: ChannelLayout) {
 		unsafe { (*self.ptr).channel_layout = channel_layout.bits(); }
 	}
 
+	pub fn sample_rate(&self) -> i32 { self.as_ref().sample_rate }
+
 	pub fn set_sample_rate(&mut self, sample_rate: i32) {
 		self.as_mut_ref().sample_rate = sample_rate;
 	}
 
+	pub fn sample_format(&self) -> SampleFormat {
+		SampleFormat::from(unsafe { mem::transmute::<i32, AVSampleFormat>(self.as_ref().format) })
+	}
+
 	pub fn set_sample_format(&mut self, sample_format: SampleFormat) {
 		let sf: AVSampleFormat = sample_format.into();
 		self.as_mut_ref().format = unsafe {mem::transmute::<AVSampleFormat, i32>(sf)};
 	}
 
+	pub fn audio_format(&self) -> AudioFormat {
+		match self.as_ref().channel_layout {
+			0 => AudioFormat::unspecified(self.channels(), self.sample_format(), self.sample_rate()),
+			_ => AudioFormat::new(self.channel_layout(), self.sample_format(), self.sample_rate())
+		}
+	}
+
+	pub fn metadata(&self, key: &str) -> Option<String> {
+		unsafe { dict_get(self.as_ref().metadata, key) }
+	}
+
+	pub fn channels(&self) -> i32 { self.as_ref().channels }
+
+	// One plane per channel for planar formats, a single interleaved plane otherwise
+	pub fn planes(&self) -> usize {
+		match self.sample_format().is_planar() {
+			true => self.channels() as usize,
+			false => 1
+		}
+	}
+
+	// Samples of plane `i`, `T` has to match the sample format
+	pub fn plane<T: Sample>(&self, i: usize) -> Result<&[T], Error> {
+		let len = self.plane_len::<T>(i)?;
+		unsafe { Ok(slice::from_raw_parts(*self.as_ref().extended_data.add(i) as *const T, len)) }
+	}
+
+	// Frames sharing their buffers with another frame are copied first, so writes don't leak into it
+	pub fn plane_mut<T: Sample>(&mut self, i: usize) -> Result<&mut [T], Error> {
+		let len = self.plane_len::<T>(i)?;
+		self.make_writable()?;
+		unsafe { Ok(slice::from_raw_parts_mut(*self.as_mut_ref().extended_data.add(i) as *mut T, len)) }
+	}
+
+	fn plane_len<T: Sample>(&self, i: usize) -> Result<usize, Error> {
+		let format = self.sample_format();
+		if format.sample_type() != Some(T::scalar_type()) {
+			return Err(Error::from(format!("Frame holds {} samples, not the requested type", format.name())));
+		}
+		if i >= self.planes() || self.as_ref().extended_data.is_null() {
+			return Err(Error::from(format!("Frame has no plane {}", i)));
+		}
+		Ok(self.nb_samples() as usize * match format.is_planar() {
+			true => 1,
+			false => self.channels() as usize
+		})
+	}
+
+	// Silences `count` samples after the first `offset`
+	pub fn set_silence(&mut self, offset: i32, count: i32) -> Result<(), Error> {
+		let (channels, sample_format) = (self.channels(), self.sample_format());
+		unsafe {
+			ffm_ret!(av_samples_set_silence(self.as_mut_ref().extended_data, offset, count, channels, sample_format.into()))?;
+		}
+		Ok(())
+	}
+
 	pub fn data_ptr(&self, i: usize) -> *const u8 { self.as_ref().data[i] }
 
 	pub fn data_mut_ptr(&mut self, i: usize) -> *mut u8 { self.as_mut_ref().data[i] }
@@ -1,5 +1,7 @@
 pub mod frame;
 pub mod packet;
+pub mod audio_fifo;
 
 pub use frame::*;
-pub use packet::*;
\ No newline at end of file
+pub use packet::*;
+pub use audio_fifo::*;
\ No newline at end of file
@@ -1,9 +1,12 @@
 use std::mem;
+use std::ptr;
+use std::slice;
 use crate::sys::*;
 use crate::error::Error;
 use super::super::format_context::FormatContext;
 use super::super::codec::*;
 use super::super::utils::*;
+use super::super::rational::*;
 
 pub struct Packet(AVPacket);
 
@@ -17,7 +20,17 @@ impl Packet {
 		}
 	}
 
-	fn as_mut_ptr(&mut self) -> *mut AVPacket { &mut self.0 }
+	// Packet owning a copy of `data`
+	pub fn from_data(data: &[u8]) -> Result<Self, Error> {
+		let mut packet = Packet::empty();
+		unsafe {
+			ffm_op!(av_new_packet(packet.as_mut_ptr(), data.len() as i32))?;
+			ptr::copy_nonoverlapping(data.as_ptr(), packet.0.data, data.len());
+		}
+		Ok(packet)
+	}
+
+	pub(crate) fn as_mut_ptr(&mut self) -> *mut AVPacket { &mut self.0 }
 
 	pub fn read(&mut self, fmt: &FormatContext) -> Result<(), Error> {
 		unsafe { ffm_op!(av_read_frame(fmt.as_mut_ptr(), self.as_mut_ptr())) }
@@ -27,18 +40,56 @@ impl Packet {
 		unsafe { ffm_op!(av_write_frame(fmt.as_mut_ptr(), self.as_mut_ptr())) }
 	}
 
+	// Buffers packets as needed so the streams of the output are interleaved by timestamp
+	pub fn write_interleaved(&mut self, fmt: &FormatContext) -> Result<(), Error> {
+		unsafe { ffm_op!(av_interleaved_write_frame(fmt.as_mut_ptr(), self.as_mut_ptr())) }
+	}
+
 	pub fn send(&mut self, ctx: &DecoderOpen) -> Result<(), Error> {
 		unsafe { ffm_op!(avcodec_send_packet(ctx.as_mut_ptr(), self.as_mut_ptr())) }
 	}
 
+	pub fn send_flush(ctx: &DecoderOpen) -> Result<(), Error> {
+		unsafe { ffm_op!(avcodec_send_packet(ctx.as_mut_ptr(), ptr::null())) }
+	}
+
 	pub fn recieve(&mut self, ctx: &mut EncoderOpen) -> Result<(), Error> {
 		unsafe { ffm_op!(avcodec_receive_packet(ctx.as_mut_ptr(), self.as_mut_ptr())) }
 	}
 
+	pub fn pts(&self) -> i64 { self.0.pts }
+
+	pub fn set_pts(&mut self, pts: i64) { self.0.pts = pts; }
+
+	pub fn dts(&self) -> i64 { self.0.dts }
+
+	pub fn set_dts(&mut self, dts: i64) { self.0.dts = dts; }
+
+	pub fn duration(&self) -> i64 { self.0.duration }
+
+	pub fn is_keyframe(&self) -> bool { self.0.flags & AV_PKT_FLAG_KEY as i32 != 0 }
+
+	pub fn size(&self) -> usize { self.0.size as usize }
+
+	pub fn data(&self) -> &[u8] {
+		match self.0.data.is_null() {
+			true => &[],
+			false => unsafe { slice::from_raw_parts(self.0.data, self.0.size as usize) }
+		}
+	}
+
 	pub fn stream_id(&self) -> i32 {
 		self.0.stream_index
 	}
 
+	pub fn set_stream_index(&mut self, index: i32) {
+		self.0.stream_index = index;
+	}
+
+	pub fn rescale_ts(&mut self, src: Rational, dst: Rational) {
+		unsafe { av_packet_rescale_ts(&mut self.0, src.into(), dst.into()); }
+	}
+
 	pub fn reset(&mut self) {
 		unsafe { av_packet_unref(&mut self.0); }
 	}
@@ -1,6 +1,8 @@
 use crate::error::Error;
 use std::{ptr, mem};
-use libc::{c_int};
+use std::time::Duration;
+use std::ffi::CString;
+use libc::{c_int, c_void};
 use crate::sys::*;
 use super::format::*;
 use super::frame::*;
@@ -12,10 +14,16 @@ pub struct Converter {
 	ptr: *mut SwrContext,
 	src_channel_ptrs: Vec<*const u8>,
 	dst_channel_ptrs: Vec<*mut u8>,
+	dst_sample_rate: i32,
 }
 
 impl Converter {
 	pub fn new(src_fmt: AudioFormat, dst_fmt: AudioFormat) -> Result<Self, Error> {
+		Self::with_channel_map(src_fmt, dst_fmt, None)
+	}
+
+	// `channel_map[i]` is the input channel feeding output channel `i`
+	pub fn with_channel_map(src_fmt: AudioFormat, dst_fmt: AudioFormat, channel_map: Option<&[i32]>) -> Result<Self, Error> {
 		unsafe {
 			let ptr = swr_alloc_set_opts(
 				ptr::null_mut(),
@@ -26,14 +34,20 @@ impl Converter {
 			if ptr.is_null() {
 				return Err(Error::from("Couldn't create the SwrContext"));
 			}
+			// Without a layout swr only knows the channel count from these
+			ffm_op!(av_opt_set_int(ptr as *mut c_void, cstr!("ich"), src_fmt.channels() as i64, 0))?;
+			ffm_op!(av_opt_set_int(ptr as *mut c_void, cstr!("och"), dst_fmt.channels() as i64, 0))?;
 
+			if let Some(channel_map) = channel_map {
+				ffm_op!(swr_set_channel_mapping(ptr, channel_map.as_ptr()))?;
+			}
 			ffm_op!(swr_init(ptr))?;
 			let src_ch_ptr_count = match src_fmt.sample_format.is_planar() {
-				true => src_fmt.channel_layout.channels() as usize,
+				true => src_fmt.channels() as usize,
 				false => 1,
 			};
 			let dst_ch_ptr_count = match dst_fmt.sample_format.is_planar() {
-				true => dst_fmt.channel_layout.channels() as usize,
+				true => dst_fmt.channels() as usize,
 				false => 1,
 			};
 
@@ -41,14 +55,57 @@ impl Converter {
 				ptr,
 				src_channel_ptrs: vec![ptr::null(); src_ch_ptr_count],
 				dst_channel_ptrs: vec![ptr::null_mut(); dst_ch_ptr_count],
+				dst_sample_rate: dst_fmt.sample_rate,
 			})
 		}
 	}
 
+	// Resampler option such as ("filter_size", "64"), ("cutoff", "0.98") or ("resampler", "soxr"). Set
+	// before converting, the context is reinitialized with it
+	pub fn set_option(&mut self, key: &str, value: &str) -> Result<(), Error> {
+		let ckey = CString::new(key).map_err(|_| Error::from(format!("Invalid option key: {}", key)))?;
+		let cvalue = CString::new(value).map_err(|_| Error::from(format!("Invalid option value: {}", value)))?;
+		unsafe {
+			swr_close(self.ptr);
+			ffm_op!(av_opt_set(self.ptr as *mut c_void, ckey.as_ptr(), cvalue.as_ptr(), 0))?;
+			ffm_op!(swr_init(self.ptr))
+		}
+	}
+
+	// `matrix[o][i]` is the gain of input channel `i` in output channel `o`, replacing the default down- or
+	// upmix. E.g. [[0.5, 0.5], [0.5, -0.5]] turns stereo into mid and side
+	pub fn set_mix_matrix<R: AsRef<[f64]>>(&mut self, matrix: &[R]) -> Result<(), Error> {
+		let inputs = matrix.first().map_or(0, |r| r.as_ref().len());
+		if matrix.iter().any(|r| r.as_ref().len() != inputs) {
+			return Err(Error::from("The rows of the mix matrix differ in length"));
+		}
+		let coefficients: Vec<f64> = matrix.iter().flat_map(|r| r.as_ref().iter().cloned()).collect();
+		unsafe {
+			swr_close(self.ptr);
+			ffm_op!(swr_set_matrix(self.ptr, coefficients.as_ptr(), inputs as c_int))?;
+			ffm_op!(swr_init(self.ptr))
+		}
+	}
+
+	// Upper bound of the samples converting `input_sample_count` more yields, including those buffered
 	pub fn get_sample_count(&self, input_sample_count: i32) -> i32 {
 		unsafe { swr_get_out_samples(self.ptr, input_sample_count) }
 	}
 
+	// Upper bound of the samples a flush yields at the end of the stream
+	pub fn flush_sample_count(&self) -> i32 { self.get_sample_count(0) }
+
+	// Input buffered by the resampler in output samples, the output lags the input by this much
+	pub fn delay(&self) -> i64 {
+		unsafe { swr_get_delay(self.ptr, self.dst_sample_rate as i64) }
+	}
+
+	// The delay in time, rounded up to whole microseconds
+	pub fn latency(&self) -> Duration {
+		let rate = self.dst_sample_rate.max(1) as i64;
+		Duration::from_micros(((self.delay().max(0) * 1_000_000 + rate - 1) / rate) as u64)
+	}
+
 	pub fn convert_frame<'a, T, C, CS, L, LS>(&mut self, input: &mut Frame, output: &mut SliceMut<'a, T, C, CS, L, LS>) -> Result<i32, Error>
 		where T: Sample, C: Dim, CS: Dim, L: Dim, LS: Dim
 	{
@@ -79,6 +136,44 @@ impl Converter {
 		}
 	}
 
+	// Writes after the first `offset` samples of the frame, so a frame can be filled over several calls
+	pub fn convert_slice_at<'a, T, C, CS, L, LS>(&mut self, input: &Slice<'a, T, C, CS, L, LS>, output: &mut Frame, offset: i32)
+		-> Result<i32, Error>
+		where T: Sample, C: Dim, CS: Dim, L: Dim, LS: Dim
+	{
+		unsafe {
+			for i in 0..self.src_channel_ptrs.len() {
+				self.src_channel_ptrs[i] = mem::transmute(input.as_row_ptr(i));
+			}
+			self.point_at_frame(output, offset);
+
+			self.convert(
+				self.src_channel_ptrs.as_ptr(), input.cols() as i32,
+				self.dst_channel_ptrs.as_ptr(), output.nb_samples() - offset
+			)
+		}
+	}
+
+	// Drains the samples buffered by the resampler into the frame after its first `offset` samples
+	pub fn flush_frame(&mut self, output: &mut Frame, offset: i32) -> Result<i32, Error> {
+		unsafe {
+			self.point_at_frame(output, offset);
+			self.convert(ptr::null(), 0, self.dst_channel_ptrs.as_ptr(), output.nb_samples() - offset)
+		}
+	}
+
+	pub fn flush_slice<'a, T, C, CS, L, LS>(&mut self, output: &mut SliceMut<'a, T, C, CS, L, LS>) -> Result<i32, Error>
+		where T: Sample, C: Dim, CS: Dim, L: Dim, LS: Dim
+	{
+		unsafe {
+			for i in 0..self.dst_channel_ptrs.len() {
+				self.dst_channel_ptrs[i] = mem::transmute(output.as_row_ptr_mut(i));
+			}
+
+			self.convert(ptr::null(), 0, self.dst_channel_ptrs.as_ptr(), output.cols() as i32)
+		}
+	}
+
 	pub fn convert(
 		&mut self,
 		input: *const *const u8,
@@ -97,6 +192,17 @@ impl Converter {
 			}
 		}
 	}
+
+	unsafe fn point_at_frame(&mut self, frame: &mut Frame, offset: i32) {
+		let sample_format = frame.sample_format();
+		let stride = match sample_format.is_planar() {
+			true => sample_format.bytes(),
+			false => sample_format.bytes() * frame.channels() as usize,
+		};
+		for i in 0..self.dst_channel_ptrs.len() {
+			self.dst_channel_ptrs[i] = frame.data_mut_ptr(i).add(offset as usize * stride);
+		}
+	}
 }
 
 impl Drop for Converter {
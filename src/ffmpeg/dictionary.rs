@@ -0,0 +1,72 @@
+use std::ptr;
+use std::ffi::{CString, CStr};
+use crate::sys::*;
+use crate::error::Error;
+
+pub struct Dictionary {
+	ptr: *mut AVDictionary,
+}
+
+impl Dictionary {
+	pub fn new() -> Self {
+		Dictionary { ptr: ptr::null_mut() }
+	}
+
+	pub fn from_pairs<'a, I>(pairs: I) -> Result<Self, Error>
+		where I: IntoIterator<Item=&'a (String, String)>
+	{
+		let mut dict = Dictionary::new();
+		for (key, value) in pairs {
+			dict.set(key, value)?;
+		}
+		Ok(dict)
+	}
+
+	pub fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+		let ckey = CString::new(key).map_err(|_| Error::from(format!("Invalid option key: {}", key)))?;
+		let cvalue = CString::new(value).map_err(|_| Error::from(format!("Invalid option value: {}", value)))?;
+		unsafe { ffm_ret!(av_dict_set(&mut self.ptr, ckey.as_ptr(), cvalue.as_ptr(), 0))?; }
+		Ok(())
+	}
+
+	pub fn as_mut_ptr_ref(&mut self) -> &mut *mut AVDictionary { &mut self.ptr }
+}
+
+impl Drop for Dictionary {
+	fn drop(&mut self) {
+		unsafe { av_dict_free(&mut self.ptr) }
+	}
+}
+
+pub(crate) unsafe fn dict_get(dict: *const AVDictionary, key: &str) -> Option<String> {
+	let ckey = CString::new(key).ok()?;
+	let entry = av_dict_get(dict, ckey.as_ptr(), ptr::null(), 0);
+	match entry.is_null() {
+		true => None,
+		false => Some(CStr::from_ptr((*entry).value).to_string_lossy().into_owned())
+	}
+}
+
+pub(crate) unsafe fn dict_set(dict: &mut *mut AVDictionary, key: &str, value: &str) -> Result<(), Error> {
+	let ckey = CString::new(key).map_err(|_| Error::from(format!("Invalid metadata key: {}", key)))?;
+	let cvalue = CString::new(value).map_err(|_| Error::from(format!("Invalid metadata value: {}", value)))?;
+	ffm_ret!(av_dict_set(dict, ckey.as_ptr(), cvalue.as_ptr(), 0))?;
+	Ok(())
+}
+
+// All entries in insertion order
+pub(crate) unsafe fn dict_entries(dict: *const AVDictionary) -> Vec<(String, String)> {
+	let mut entries = Vec::new();
+	let mut entry = ptr::null();
+	let empty = CString::new("").unwrap();
+	loop {
+		entry = av_dict_get(dict, empty.as_ptr(), entry, AV_DICT_IGNORE_SUFFIX as i32);
+		if entry.is_null() {
+			return entries;
+		}
+		entries.push((
+			CStr::from_ptr((*entry).key).to_string_lossy().into_owned(),
+			CStr::from_ptr((*entry).value).to_string_lossy().into_owned()
+		));
+	}
+}
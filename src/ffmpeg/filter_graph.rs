@@ -0,0 +1,111 @@
+use std::ptr;
+use std::ffi::CString;
+use crate::sys::*;
+use crate::error::Error;
+use super::format::*;
+use super::containers::*;
+use super::utils::*;
+
+pub struct FilterGraph {
+	ptr: *mut AVFilterGraph,
+	src: *mut AVFilterContext,
+	sink: *mut AVFilterContext,
+}
+
+impl FilterGraph {
+	pub fn new(src_fmt: &AudioFormat, dst_fmt: &AudioFormat, description: &str) -> Result<Self, Error> {
+		unsafe {
+			let ptr = avfilter_graph_alloc();
+			if ptr.is_null() {
+				return Err(Error::from("Couldn't allocate the filter graph"));
+			}
+			let mut graph = FilterGraph { ptr, src: ptr::null_mut(), sink: ptr::null_mut() };
+
+			let src_layout = match src_fmt.is_unspecified() {
+				true => String::new(),
+				false => format!(":channel_layout=0x{:x}", src_fmt.channel_layout.bits())
+			};
+			let src_args = CString::new(format!(
+				"time_base=1/{}:sample_rate={}:sample_fmt={}:channels={}{}",
+				src_fmt.sample_rate, src_fmt.sample_rate, src_fmt.sample_format.name(), src_fmt.channels(), src_layout
+			)).unwrap();
+			ffm_ret!(avfilter_graph_create_filter(
+				&mut graph.src, avfilter_get_by_name(cstr!("abuffer")), cstr!("in"),
+				src_args.as_ptr(), ptr::null_mut(), graph.ptr
+			))?;
+			ffm_ret!(avfilter_graph_create_filter(
+				&mut graph.sink, avfilter_get_by_name(cstr!("abuffersink")), cstr!("out"),
+				ptr::null(), ptr::null_mut(), graph.ptr
+			))?;
+
+			// "4c" is a count without a layout
+			let dst_layout = match dst_fmt.is_unspecified() {
+				true => format!("{}c", dst_fmt.channels()),
+				false => format!("0x{:x}", dst_fmt.channel_layout.bits())
+			};
+			let spec = CString::new(format!(
+				"{},aformat=sample_fmts={}:sample_rates={}:channel_layouts={}",
+				description, dst_fmt.sample_format.name(), dst_fmt.sample_rate, dst_layout
+			)).unwrap();
+
+			let mut outputs = avfilter_inout_alloc();
+			let mut inputs = avfilter_inout_alloc();
+			if outputs.is_null() || inputs.is_null() {
+				avfilter_inout_free(&mut outputs);
+				avfilter_inout_free(&mut inputs);
+				return Err(Error::from("Couldn't allocate the filter graph endpoints"));
+			}
+
+			(*outputs).name = av_strdup(cstr!("in"));
+			(*outputs).filter_ctx = graph.src;
+			(*outputs).pad_idx = 0;
+			(*outputs).next = ptr::null_mut();
+
+			(*inputs).name = av_strdup(cstr!("out"));
+			(*inputs).filter_ctx = graph.sink;
+			(*inputs).pad_idx = 0;
+			(*inputs).next = ptr::null_mut();
+
+			let ret = avfilter_graph_parse_ptr(graph.ptr, spec.as_ptr(), &mut inputs, &mut outputs, ptr::null_mut());
+			avfilter_inout_free(&mut inputs);
+			avfilter_inout_free(&mut outputs);
+			ffm_ret!(ret)?;
+
+			ffm_ret!(avfilter_graph_config(graph.ptr, ptr::null_mut()))?;
+
+			Ok(graph)
+		}
+	}
+
+	pub fn push(&mut self, frame: &mut Frame) -> Result<(), Error> {
+		unsafe { ffm_op!(av_buffersrc_add_frame(self.src, frame.as_mut_ptr())) }
+	}
+
+	pub fn push_flush(&mut self) -> Result<(), Error> {
+		unsafe { ffm_op!(av_buffersrc_add_frame(self.src, ptr::null_mut())) }
+	}
+
+	pub fn pull(&mut self, frame: &mut Frame) -> Result<(), Error> {
+		frame.reset();
+		unsafe { ffm_op!(av_buffersink_get_frame(self.sink, frame.as_mut_ptr())) }
+	}
+}
+
+impl Drop for FilterGraph {
+	fn drop(&mut self) {
+		unsafe { avfilter_graph_free(&mut self.ptr) }
+	}
+}
+
+// Escapes a filter option value for a graph description. The value is quoted for the option parser,
+// closing the quote around each quote, and the result escaped again for the graph parser
+pub fn escape_filter_option(value: &str) -> String {
+	let quoted = format!("'{}'", value.replace('\'', "'\\''"));
+	quoted.chars().fold(String::with_capacity(quoted.len() * 2), |mut escaped, c| {
+		if "\\'[],;".contains(c) {
+			escaped.push('\\');
+		}
+		escaped.push(c);
+		escaped
+	})
+}
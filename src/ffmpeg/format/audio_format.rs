@@ -1,22 +1,101 @@
+use std::fmt;
+use std::str::FromStr;
 use litaudio::*;
+use crate::error::Error;
 use super::channel_layout::*;
 use super::sample_format::*;
 
-#[derive(new)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct AudioFormat {
+	// Empty when the channels have no speaker positions, e.g. ambisonic components
 	pub channel_layout: ChannelLayout,
+	pub channels: i32,
 	pub sample_format: SampleFormat,
 	pub sample_rate: i32
 }
 
 impl AudioFormat {
+	pub fn new(channel_layout: ChannelLayout, sample_format: SampleFormat, sample_rate: i32) -> Self {
+		AudioFormat { channel_layout, channels: channel_layout.channels(), sample_format, sample_rate }
+	}
+
+	// Channels without a layout, converters keep them as they are rather than remixing a guessed layout
+	pub fn unspecified(channels: i32, sample_format: SampleFormat, sample_rate: i32) -> Self {
+		AudioFormat { channel_layout: ChannelLayout::empty(), channels, sample_format, sample_rate }
+	}
+
+	pub fn is_unspecified(&self) -> bool { self.channel_layout.is_empty() }
 	pub fn from_storage<T, P, S>(s: &S) -> Self
 		where T: Sample, P: SamplePackingType, S: AudioStorage<T, P>
 	{
 		AudioFormat {
 			channel_layout: ChannelLayout::default(s.channels() as i32),
+			channels: s.channels() as i32,
 			sample_format: SampleFormat::from_storage(s),
 			sample_rate: s.sample_rate()
 		}
 	}
+
+	#[inline]
+	pub fn channels(&self) -> i32 { self.channels }
+
+	pub fn needs_resampling(&self, other: &AudioFormat) -> bool { self.sample_rate != other.sample_rate }
+
+	// An unspecified layout matches any layout with as many channels
+	pub fn needs_remixing(&self, other: &AudioFormat) -> bool {
+		self.channels != other.channels
+			|| (!self.is_unspecified() && !other.is_unspecified() && self.channel_layout != other.channel_layout)
+	}
+
+	// Whether samples convert to `other` one by one, without resampling or remixing
+	pub fn is_compatible(&self, other: &AudioFormat) -> bool {
+		!self.needs_resampling(other) && !self.needs_remixing(other)
+	}
+
+	// Relative cost of converting to `other`, 0 when the formats are equal. Resampling weighs the most,
+	// then remixing, then lossy sample conversion, then any sample conversion and finally repacking
+	pub fn conversion_cost(&self, other: &AudioFormat) -> u32 {
+		let (src, dst) = (&self.sample_format, &other.sample_format);
+		let mut cost = 0;
+		if self.needs_resampling(other) { cost += 16; }
+		if self.needs_remixing(other) { cost += 8; }
+		if src.sample_type() != dst.sample_type() {
+			cost += match src.is_lossless_to(dst) {
+				true => 2,
+				false => 4
+			};
+		}
+		if src.is_planar() != dst.is_planar() { cost += 1; }
+		cost
+	}
+}
+
+// Formats as "48000Hz stereo fltp", or "48000Hz 4c fltp" without a layout
+impl fmt::Display for AudioFormat {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.is_unspecified() {
+			true => write!(f, "{}Hz {}c {}", self.sample_rate, self.channels, self.sample_format.name()),
+			false => write!(f, "{}Hz {} {}", self.sample_rate, self.channel_layout, self.sample_format.name())
+		}
+	}
+}
+
+impl FromStr for AudioFormat {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let invalid = || Error::from(format!("Invalid audio format: {}", s));
+		let parts: Vec<&str> = s.split_whitespace().collect();
+		match parts.as_slice() {
+			[sample_rate, channel_layout, sample_format] => {
+				let sample_rate = sample_rate.trim_end_matches("Hz").parse().map_err(|_| invalid())?;
+				let sample_format = SampleFormat::from_name(sample_format).ok_or_else(invalid)?;
+				match channel_layout.strip_suffix('c').and_then(|c| c.parse().ok()) {
+					Some(channels) => Ok(AudioFormat::unspecified(channels, sample_format, sample_rate)),
+					None => Ok(AudioFormat::new(channel_layout.parse()?, sample_format, sample_rate))
+				}
+			},
+			_ => Err(invalid())
+		}
+	}
 }
\ No newline at end of file
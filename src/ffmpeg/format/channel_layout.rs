@@ -1,5 +1,9 @@
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::str::FromStr;
 use crate::sys::*;
-use libc::c_ulonglong;
+use crate::error::Error;
+use libc::{c_char, c_ulonglong};
 
 bitflags! {
     pub struct ChannelLayout: c_ulonglong {
@@ -58,6 +62,12 @@ bitflags! {
         const OCTAGONAL          = AV_CH_LAYOUT_OCTAGONAL;
         const HEXADECAGONAL      = AV_CH_LAYOUT_HEXADECAGONAL;
         const STEREO_DOWNMIX     = AV_CH_LAYOUT_STEREO_DOWNMIX;
+
+        // Immersive layouts with height channels, FFmpeg 4 has no constants for these
+        const _5POINT1POINT2     = Self::_5POINT1_BACK.bits | Self::TOP_FRONT_LEFT.bits | Self::TOP_FRONT_RIGHT.bits;
+        const _5POINT1POINT4     = Self::_5POINT1POINT2.bits | Self::TOP_BACK_LEFT.bits | Self::TOP_BACK_RIGHT.bits;
+        const _7POINT1POINT2     = Self::_7POINT1.bits | Self::TOP_FRONT_LEFT.bits | Self::TOP_FRONT_RIGHT.bits;
+        const _7POINT1POINT4     = Self::_7POINT1POINT2.bits | Self::TOP_BACK_LEFT.bits | Self::TOP_BACK_RIGHT.bits;
     }
 }
 
@@ -67,9 +77,122 @@ impl ChannelLayout {
         unsafe { av_get_channel_layout_nb_channels(self.bits()) }
     }
 
-    pub fn default(number: i32) -> ChannelLayout {
+    // Parses FFmpeg layout names such as "5.1(side)", channel lists like "FL+FR" or a channel count
+    pub fn from_name(name: &str) -> Option<ChannelLayout> {
+        let name = CString::new(name).ok()?;
+        let bits = unsafe { av_get_channel_layout(name.as_ptr()) };
+        match bits {
+            0 => None,
+            bits => Some(ChannelLayout::from_bits_truncate(bits))
+        }
+    }
+
+    #[inline]
+    pub fn from_mask(mask: u64) -> ChannelLayout {
+        ChannelLayout::from_bits_truncate(mask as c_ulonglong)
+    }
+
+    #[inline]
+    pub fn mask(&self) -> u64 {
+        self.bits() as u64
+    }
+
+    // Layout name such as "stereo", or channel list when the layout has no name
+    pub fn name(&self) -> String {
+        let mut buf = [0 as c_char; 128];
         unsafe {
+            av_get_channel_layout_string(buf.as_mut_ptr(), buf.len() as i32, self.channels(), self.bits());
+            CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned()
+        }
+    }
+
+    // Single channel layouts of each position in channel order
+    pub fn iter(&self) -> ChannelIter {
+        ChannelIter { layout: *self, index: 0 }
+    }
+
+    // Abbreviated channel names such as "FL" in channel order
+    pub fn channel_names(&self) -> Vec<&'static str> {
+        self.iter().filter_map(|c| c.channel_name()).collect()
+    }
+
+    // Name of the position of a single channel layout
+    pub fn channel_name(&self) -> Option<&'static str> {
+        unsafe {
+            let ptr = av_get_channel_name(self.bits());
+            match ptr.is_null() {
+                true => None,
+                false => CStr::from_ptr(ptr).to_str().ok()
+            }
+        }
+    }
+
+    // Index of the single channel `channel` within this layout
+    pub fn index_of(&self, channel: ChannelLayout) -> Option<usize> {
+        let index = unsafe { av_get_channel_layout_channel_index(self.bits(), channel.bits()) };
+        match index < 0 {
+            true => None,
+            false => Some(index as usize)
+        }
+    }
+
+    // The single channel at `index` in channel order
+    pub fn channel(&self, index: usize) -> Option<ChannelLayout> {
+        let bits = unsafe { av_channel_layout_extract_channel(self.bits(), index as i32) };
+        match bits {
+            0 => None,
+            bits => Some(ChannelLayout::from_bits_truncate(bits))
+        }
+    }
+
+    // The layout with the matrix encoded downmix positions that DTS and AC-3 decoders use for Lt/Rt as
+    // plain stereo, so the two channels map onto a stereo container without remixing
+    pub fn without_downmix(&self) -> ChannelLayout {
+        match *self == ChannelLayout::STEREO_DOWNMIX {
+            true => ChannelLayout::STEREO,
+            false => *self
+        }
+    }
+
+    // Falls back to the immersive layouts for counts FFmpeg has no default for. Layouts needing
+    // positions missing from the FFmpeg 4 mask (e.g. 22.2) are empty and written without positions
+    pub fn default(number: i32) -> ChannelLayout {
+        let layout = unsafe {
             ChannelLayout::from_bits_truncate(av_get_default_channel_layout(number) as c_ulonglong)
+        };
+        match (layout.is_empty(), number) {
+            (true, 10) => ChannelLayout::_5POINT1POINT4,
+            (true, 12) => ChannelLayout::_7POINT1POINT4,
+            _ => layout
         }
     }
 }
+
+pub struct ChannelIter {
+    layout: ChannelLayout,
+    index: usize,
+}
+
+impl Iterator for ChannelIter {
+    type Item = ChannelLayout;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let channel = self.layout.channel(self.index)?;
+        self.index += 1;
+        Some(channel)
+    }
+}
+
+impl fmt::Display for ChannelLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.name())
+    }
+}
+
+impl FromStr for ChannelLayout {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ChannelLayout::from_name(s).ok_or_else(|| Error::from(format!("Unknown channel layout: {}", s)))
+    }
+}
@@ -1,8 +1,10 @@
 use super::sample_format::*;
 
 pub fn pick_best_format(iter: FormatIter, format: SampleFormat) -> Option<SampleFormat> {
-	iter.max_by_key(|f| {
-		((f.sample_type() == format.sample_type()) as i32) * 2
-			+ ((f.is_planar() == format.is_planar()) as i32)
-	})
-}
\ No newline at end of file
+	SampleFormat::negotiate(format, iter)
+}
+
+// The supported rate closest to `sample_rate`, the higher one on a tie so no bandwidth is lost
+pub fn pick_sample_rate(supported: &[i32], sample_rate: i32) -> Option<i32> {
+	supported.iter().cloned().min_by_key(|r| ((*r as i64 - sample_rate as i64).abs(), -r))
+}
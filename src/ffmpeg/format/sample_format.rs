@@ -32,6 +32,15 @@ impl SampleFormat {
         }
     }
 
+    // Parses FFmpeg sample format names such as "fltp"
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = CString::new(name).ok()?;
+        match unsafe { SampleFormat::from(av_get_sample_fmt(name.as_ptr())) } {
+            SampleFormat::None => None,
+            format => Some(format)
+        }
+    }
+
     #[inline]
     pub fn sample_type(&self) -> Option<ScalarType> {
         match self {
@@ -70,6 +79,81 @@ impl SampleFormat {
         unsafe { av_get_bytes_per_sample((*self).into()) as usize }
     }
 
+    #[inline]
+    pub fn bits(&self) -> usize {
+        self.bytes() * 8
+    }
+
+    #[inline]
+    pub fn is_float(&self) -> bool {
+        match self {
+            SampleFormat::F32(_) | SampleFormat::F64(_) => true,
+            _ => false
+        }
+    }
+
+    #[inline]
+    pub fn is_signed(&self) -> bool {
+        match self {
+            SampleFormat::None | SampleFormat::U8(_) => false,
+            _ => true
+        }
+    }
+
+    // Bits of precision a sample holds, the mantissa and sign for floats
+    fn precision(&self) -> usize {
+        match self {
+            SampleFormat::F32(_) => 25,
+            SampleFormat::F64(_) => 54,
+            _ => self.bits()
+        }
+    }
+
+    // Whether every sample of this format is represented exactly in `other`, regardless of packing
+    pub fn is_lossless_to(&self, other: &SampleFormat) -> bool {
+        match (self, other) {
+            (SampleFormat::None, _) | (_, SampleFormat::None) => false,
+            _ if self.is_float() && !other.is_float() => false,
+            // Unsigned 8 bit is offset into the signed range, requiring one bit more
+            (SampleFormat::U8(_), _) if !other.is_float() => other.precision() > 8,
+            _ => self.precision() <= other.precision()
+        }
+    }
+
+    // Picks the available format closest to `wanted`: the same type first, then a lossless
+    // conversion to the smallest format, then the most precise format
+    pub fn negotiate<I>(wanted: SampleFormat, available: I) -> Option<SampleFormat>
+        where I: IntoIterator<Item=SampleFormat>
+    {
+        available.into_iter().max_by_key(|f| {
+            let same_type = f.sample_type() == wanted.sample_type();
+            // Only a conversion from `wanted` matters, a narrower format that fits into it still loses samples
+            let lossless = wanted.is_lossless_to(f);
+            (
+                same_type,
+                lossless,
+                // Among lossless conversions the smallest wins, otherwise the most precise
+                match lossless {
+                    true => -(f.precision() as i64),
+                    false => f.precision() as i64
+                },
+                f.is_planar() == wanted.is_planar()
+            )
+        })
+    }
+
+    pub fn pcm_codec_name(&self) -> Option<&'static str> {
+        match self {
+            SampleFormat::None => None,
+            SampleFormat::U8(_) => Some("pcm_u8"),
+            SampleFormat::I16(_) => Some("pcm_s16le"),
+            SampleFormat::I32(_) => Some("pcm_s32le"),
+            SampleFormat::I64(_) => Some("pcm_s64le"),
+            SampleFormat::F32(_) => Some("pcm_f32le"),
+            SampleFormat::F64(_) => Some("pcm_f64le"),
+        }
+    }
+
     pub fn from_type<T, P>() -> Self
         where T: Sample, P: SamplePackingType
     {
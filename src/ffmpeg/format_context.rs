@@ -1,9 +1,16 @@
-use std::ptr;
+use std::{mem, ptr};
+use std::sync::Once;
+use std::time::Duration;
 use crate::sys::*;
 use crate::error::Error;
 use super::utils::*;
 use super::stream::*;
-use std::ffi::CString;
+use super::dictionary::*;
+use super::log::*;
+use super::rational::*;
+use super::io_context::*;
+use std::ffi::{CString, CStr};
+use libc::{c_char, c_int, c_void};
 
 #[derive(Copy, Clone, Debug)]
 pub enum Mode {
@@ -13,12 +20,23 @@ pub enum Mode {
 
 pub struct FormatContext {
 	ptr: *mut AVFormatContext,
-	mode: Mode
+	mode: Mode,
+	// Custom IO in place of the URL, dropped after the context is closed
+	io: Option<IoContext>,
 }
 
 impl FormatContext {
 	pub fn new(ptr: *mut AVFormatContext, mode: Mode) -> Self {
-		FormatContext { ptr, mode }
+		FormatContext { ptr, mode, io: None }
+	}
+
+	// Reads or writes through `io`, before the input is opened or the header written
+	pub fn set_io(&mut self, mut io: IoContext) {
+		unsafe {
+			(*self.ptr).pb = io.as_mut_ptr();
+			(*self.ptr).flags |= AVFMT_FLAG_CUSTOM_IO as c_int;
+		}
+		self.io = Some(io);
 	}
 
 	pub fn get_audio_stream(&self) -> Option<Stream> {
@@ -33,10 +51,159 @@ impl FormatContext {
 		}
 	}
 
+	pub fn programs(&self) -> Vec<Program> {
+		unsafe {
+			(0..self.as_ref().nb_programs as isize)
+				.filter_map(|i| Program::new(*self.as_ref().programs.offset(i)))
+				.collect()
+		}
+	}
+
+	pub fn chapters(&self) -> Vec<Chapter> {
+		unsafe {
+			(0..self.as_ref().nb_chapters as isize)
+				.filter_map(|i| Chapter::new(*self.as_ref().chapters.offset(i)))
+				.collect()
+		}
+	}
+
+	// Adds a chapter to an output before its header is written
+	pub fn add_chapter(&mut self, start: Duration, end: Duration, title: Option<&str>) -> Result<(), Error> {
+		unsafe {
+			let chapter = av_mallocz(mem::size_of::<AVChapter>()) as *mut AVChapter;
+			if chapter.is_null() {
+				return Err(Error::from("Could not allocate a chapter"));
+			}
+			(*chapter).id = self.as_ref().nb_chapters as _;
+			(*chapter).time_base = Rational::MICROS.into();
+			(*chapter).start = Rational::MICROS.from_duration(start);
+			(*chapter).end = Rational::MICROS.from_duration(end);
+			av_dynarray_add(
+				&mut (*self.ptr).chapters as *mut *mut *mut AVChapter as *mut c_void,
+				&mut (*self.ptr).nb_chapters as *mut u32 as *mut c_int,
+				chapter as *mut c_void
+			);
+			if self.as_ref().chapters.is_null() {
+				return Err(Error::from("Could not add the chapter"));
+			}
+			match title {
+				None => Ok(()),
+				Some(title) => dict_set(&mut (*chapter).metadata, "title", title)
+			}
+		}
+	}
+
+	// Stores a file in the container, as Matroska does with fonts and images
+	pub fn add_attachment(&mut self, filename: &str, mime_type: &str, data: &[u8]) -> Result<(), Error> {
+		let mut stream = Stream::from_format(self)?;
+		let mut params = stream.parameters();
+		params.as_mut_ref().codec_type = AVMediaType::AVMEDIA_TYPE_ATTACHMENT;
+		params.as_mut_ref().codec_id = AVCodecID::AV_CODEC_ID_BIN_DATA;
+		params.set_extradata(data)?;
+		stream.set_metadata("filename", filename)?;
+		stream.set_metadata("mimetype", mime_type)
+	}
+
+	pub fn streams(&self) -> Vec<Stream> {
+		unsafe {
+			(0..self.as_ref().nb_streams as isize)
+				.filter_map(|i| Stream::new(*self.as_ref().streams.offset(i)))
+				.collect()
+		}
+	}
+
+	pub fn metadata(&self, key: &str) -> Option<String> {
+		unsafe { dict_get(self.as_ref().metadata, key) }
+	}
+
+	pub fn metadata_entries(&self) -> Vec<(String, String)> {
+		unsafe { dict_entries(self.as_ref().metadata) }
+	}
+
+	pub fn copy_metadata_from(&mut self, other: &FormatContext) -> Result<(), Error> {
+		unsafe { ffm_ret!(av_dict_copy(&mut (*self.ptr).metadata, other.as_ref().metadata, 0))?; }
+		Ok(())
+	}
+
+	pub fn set_metadata(&mut self, key: &str, value: &str) -> Result<(), Error> {
+		unsafe { dict_set(&mut (*self.ptr).metadata, key, value) }
+	}
+
+	// Audio streams, restricted to those of the program with the given number
+	pub fn audio_streams(&self, program: Option<i32>) -> Vec<Stream> {
+		let indices = program.map(|number| self.programs().into_iter()
+			.find(|p| p.number() == number)
+			.map(|p| p.stream_indices())
+			.unwrap_or_default()
+		);
+		self.streams().into_iter()
+			.filter(|s| s.parameters().is_audio())
+			.filter(|s| indices.as_ref().map_or(true, |i| i.contains(&s.index())))
+			.collect()
+	}
+
+	// Restricted to the streams of the program with the given number, or the stream with the given id (the PID for TS)
+	pub fn find_audio_stream(&self, program: Option<i32>, stream_id: Option<i32>) -> Option<Stream> {
+		let candidates: Vec<Stream> = match program {
+			None => self.streams(),
+			Some(number) => {
+				let indices = self.programs().into_iter().find(|p| p.number() == number)?.stream_indices();
+				self.streams().into_iter().filter(|s| indices.contains(&s.index())).collect()
+			}
+		};
+
+		match (program, stream_id) {
+			(None, None) => self.get_audio_stream(),
+			(_, Some(id)) => candidates.into_iter().find(|s| s.id() == id && s.parameters().is_audio()),
+			(_, None) => candidates.into_iter().find(|s| s.parameters().is_audio()),
+		}
+	}
+
+	// None when neither the container nor the streams store a duration
+	pub fn duration(&self) -> Option<Duration> { self.duration_ts().and_then(|ts| Rational::MICROS.to_duration(ts)) }
+
+	// Duration in microseconds
+	pub fn duration_ts(&self) -> Option<i64> {
+		match self.as_ref().duration {
+			d if d <= 0 => None,
+			d => Some(d)
+		}
+	}
+
+	// Overall bit rate in bits per second, estimated from the file size and duration for some formats
+	pub fn bit_rate(&self) -> Option<i64> {
+		match self.as_ref().bit_rate {
+			b if b <= 0 => None,
+			b => Some(b)
+		}
+	}
+
 	pub fn get_duration(&self) -> usize {
 		self.as_ref().duration as usize
 	}
 
+	// Short name of the demuxer, e.g. "mov,mp4,m4a,3gp,3g2,mj2"
+	pub fn input_format_name(&self) -> Option<String> {
+		unsafe {
+			let format = self.as_ref().iformat;
+			match format.is_null() {
+				true => None,
+				false => Some(CStr::from_ptr((*format).name).to_string_lossy().into_owned())
+			}
+		}
+	}
+
+	// Short name of the muxer, e.g. "mp4"
+	pub fn output_format_name(&self) -> Option<String> {
+		unsafe {
+			let format = self.as_ref().oformat;
+			match format.is_null() {
+				true => None,
+				false => Some(CStr::from_ptr((*format).name).to_string_lossy().into_owned())
+			}
+		}
+	}
+
 	pub fn get_output_format(&self) -> *mut AVOutputFormat {
 		self.as_ref().oformat
 	}
@@ -45,6 +212,48 @@ impl FormatContext {
 		unsafe { (*self.as_ref().oformat).flags }
 	}
 
+	pub fn write_header(&self, options: &mut Dictionary) -> Result<(), Error> {
+		unsafe { ffm_ret!(avformat_write_header(self.ptr, options.as_mut_ptr_ref()))?; }
+		Ok(())
+	}
+
+	pub fn write_trailer(&self) -> Result<(), Error> {
+		unsafe { ffm_op!(av_write_trailer(self.ptr)) }
+	}
+
+	pub fn seek(&self, stream_index: i32, timestamp: i64) -> Result<(), Error> {
+		unsafe { ffm_ret!(av_seek_frame(self.ptr, stream_index, timestamp, AVSEEK_FLAG_BACKWARD as i32))?; }
+		Ok(())
+	}
+
+	pub fn icy_metadata(&self) -> Option<String> {
+		unsafe {
+			let pb = self.as_ref().pb;
+			if pb.is_null() {
+				return None;
+			}
+
+			let mut value: *mut u8 = ptr::null_mut();
+			let ret = av_opt_get(pb as *mut c_void, cstr!("icy_metadata_packet"), AV_OPT_SEARCH_CHILDREN as i32, &mut value);
+			if ret < 0 || value.is_null() {
+				return None;
+			}
+
+			let metadata = CStr::from_ptr(value as *const c_char).to_string_lossy().into_owned();
+			av_free(value as *mut c_void);
+			Some(metadata)
+		}
+	}
+
+	pub fn sdp(&self) -> Result<String, Error> {
+		let mut buf = vec![0 as c_char; 16384];
+		let mut ptr = self.ptr;
+		unsafe {
+			ffm_op!(av_sdp_create(&mut ptr, 1, buf.as_mut_ptr(), buf.len() as i32))?;
+			Ok(CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned())
+		}
+	}
+
 	pub fn dump(&self, url: &str) {
 		unsafe {
 			let cpath = CString::new(url).unwrap();
@@ -53,6 +262,46 @@ impl FormatContext {
 	}
 }
 
+ff_wrap_struct!(Chapter, AVChapter);
+ff_wrap!(Chapter, AVChapter);
+
+impl Chapter {
+	pub fn start(&self) -> Duration {
+		Rational::from(self.as_ref().time_base).to_duration(self.as_ref().start).unwrap_or_default()
+	}
+
+	pub fn end(&self) -> Duration {
+		Rational::from(self.as_ref().time_base).to_duration(self.as_ref().end).unwrap_or_default()
+	}
+
+	pub fn title(&self) -> Option<String> { self.metadata("title") }
+
+	pub fn metadata(&self, key: &str) -> Option<String> {
+		unsafe { dict_get(self.as_ref().metadata, key) }
+	}
+}
+
+ff_wrap_struct!(Program, AVProgram);
+ff_wrap!(Program, AVProgram);
+
+impl Program {
+	pub fn id(&self) -> i32 { self.as_ref().id }
+
+	pub fn number(&self) -> i32 { self.as_ref().program_num }
+
+	pub fn stream_indices(&self) -> Vec<i32> {
+		unsafe {
+			(0..self.as_ref().nb_stream_indexes as isize)
+				.map(|i| *self.as_ref().stream_index.offset(i) as i32)
+				.collect()
+		}
+	}
+
+	pub fn metadata(&self, key: &str) -> Option<String> {
+		unsafe { dict_get(self.as_ref().metadata, key) }
+	}
+}
+
 impl FFWrapper<AVFormatContext> for FormatContext {
 	fn as_ptr(&self) -> *const AVFormatContext { self.ptr }
 
@@ -65,8 +314,9 @@ impl Drop for FormatContext {
 			match self.mode {
 				Mode::Input => avformat_close_input(&mut self.ptr),
 
+				Mode::Output if self.ptr.is_null() => {},
 				Mode::Output => {
-					if !self.as_ref().pb.is_null() {
+					if !self.as_ref().pb.is_null() && self.io.is_none() {
 						avio_close((*self.ptr).pb);
 					}
 					avformat_free_context(self.ptr);
@@ -77,37 +327,137 @@ impl Drop for FormatContext {
 }
 
 pub fn open_read(path: &str) -> Result<FormatContext, Error> {
+	open_read_with(path, None, &mut Dictionary::new())
+}
+
+pub fn open_read_with(path: &str, format: Option<&str>, options: &mut Dictionary) -> Result<FormatContext, Error> {
+	open_read_scoped(path, format, options, None)
+}
+
+// Attaches the context to `log` before probing, so the messages of opening follow its config
+pub fn open_read_scoped(
+	path: &str, format: Option<&str>, options: &mut Dictionary, log: Option<&mut LogScope>
+) -> Result<FormatContext, Error> {
+	open_input(path, None, format, options, log)
+}
+
+// Demuxes what `io` reads, the probing can't rely on a file extension so naming the format helps
+pub fn open_read_io(
+	io: IoContext, format: Option<&str>, options: &mut Dictionary, log: Option<&mut LogScope>
+) -> Result<FormatContext, Error> {
+	open_input("", Some(io), format, options, log)
+}
+
+fn open_input(
+	path: &str, io: Option<IoContext>, format: Option<&str>, options: &mut Dictionary, log: Option<&mut LogScope>
+) -> Result<FormatContext, Error> {
+	register_devices();
 	let cpath = CString::new(path).unwrap();
 
-	let mut format_context = FormatContext::new(ptr::null_mut(), Mode::Input);
+	let input_format = match format {
+		None => ptr::null_mut(),
+		Some(name) => {
+			let cname = CString::new(name).unwrap();
+			let input_format = unsafe { av_find_input_format(cname.as_ptr()) };
+			if input_format.is_null() {
+				return Err(Error::from(format!("Could not find input format: {}", name)));
+			}
+			input_format
+		}
+	};
+
+	let mut format_context = FormatContext::new(unsafe { avformat_alloc_context() }, Mode::Input);
+	if format_context.ptr.is_null() {
+		return Err(Error::from("Could not allocate the input context."));
+	}
+	if let Some(log) = log {
+		log.attach(format_context.ptr as *const c_void);
+	}
+	if let Some(io) = io {
+		format_context.set_io(io);
+	}
 
 	unsafe {
-		ffm_op!(avformat_open_input(&mut format_context.ptr, cpath.as_ptr(), ptr::null_mut(), ptr::null_mut()))?;
+		ffm_op!(avformat_open_input(&mut format_context.ptr, cpath.as_ptr(), input_format, options.as_mut_ptr_ref()))?;
+		let _span = timed_span!(DEBUG, "probe", path);
 		ffm_op!(avformat_find_stream_info(format_context.as_mut_ptr(), ptr::null_mut()))?;
 	}
 	Ok(format_context)
 }
 
+// Device formats such as lavfi, alsa or pulse are only found once libavdevice registered them
+fn register_devices() {
+	static REGISTER: Once = Once::new();
+	REGISTER.call_once(|| unsafe { avdevice_register_all() });
+}
+
 pub fn open_write(path: &str) -> Result<FormatContext, Error> {
-	let cpath = CString::new(path).unwrap();
+	open_write_with(path, None, &mut Dictionary::new())
+}
 
-	unsafe {
-		let mut output_context: *mut AVIOContext = ptr::null_mut();
+pub fn open_write_with(path: &str, format: Option<&str>, options: &mut Dictionary) -> Result<FormatContext, Error> {
+	open_output(path, format, Sink::Url(options))
+}
+
+// Muxes into `io`, `path` only serves to guess the format from its extension when none is given
+pub fn open_write_io(path: &str, format: Option<&str>, io: IoContext) -> Result<FormatContext, Error> {
+	open_output(path, format, Sink::Io(io))
+}
+
+enum Sink<'a> {
+	// Opens the path with these protocol options
+	Url(&'a mut Dictionary),
+	Io(IoContext),
+}
 
-		ffm_op!(avio_open(&mut output_context, cpath.as_ptr(), AVIO_FLAG_READ_WRITE))?;
-		let ptr = avformat_alloc_context();
-		(*ptr).pb = output_context;
+fn open_output(path: &str, format: Option<&str>, sink: Sink) -> Result<FormatContext, Error> {
+	register_devices();
+	let cpath = CString::new(path).unwrap();
 
-		(*ptr).oformat = av_guess_format(ptr::null_mut(), cpath.as_ptr(), ptr::null_mut());
-		if (*ptr).oformat.is_null() {
+	unsafe {
+		let output_format = match format {
+			None => av_guess_format(ptr::null_mut(), cpath.as_ptr(), ptr::null_mut()),
+			Some(name) => {
+				let cname = CString::new(name).unwrap();
+				av_guess_format(cname.as_ptr(), ptr::null_mut(), ptr::null_mut())
+			}
+		};
+		if output_format.is_null() {
 			return Err(Error::from("Could not find output file format"));
 		}
 
-		(*ptr).url = av_strdup(cpath.as_ptr());
-		if (*ptr).url.is_null() {
+		let mut format_context = FormatContext::new(avformat_alloc_context(), Mode::Output);
+		if format_context.ptr.is_null() {
+			return Err(Error::from("Could not allocate the output context."));
+		}
+		(*format_context.ptr).oformat = output_format;
+
+		(*format_context.ptr).url = av_strdup(cpath.as_ptr());
+		if (*format_context.ptr).url.is_null() {
 			return Err(Error::from("Could not allocate url."));
 		}
 
-		Ok(FormatContext::new(ptr, Mode::Output))
+		// Muxers such as segment open their own files, so they can't write into custom IO
+		if ((*output_format).flags & AVFMT_NOFILE as i32) != 0 {
+			return match sink {
+				Sink::Io(_) => Err(Error::Unsupported(format!(
+					"The {} muxer writes its own files and can't write into custom IO",
+					CStr::from_ptr((*output_format).name).to_string_lossy()
+				))),
+				Sink::Url(_) => Ok(format_context),
+			};
+		}
+		let options = match sink {
+			Sink::Io(io) => {
+				format_context.set_io(io);
+				return Ok(format_context);
+			},
+			Sink::Url(options) => options
+		};
+
+		let flags = url_write_flags(path);
+		ffm_op!(avio_open2(&mut (*format_context.ptr).pb, cpath.as_ptr(), flags, ptr::null(), options.as_mut_ptr_ref()))?;
+
+		Ok(format_context)
 	}
 }
@@ -0,0 +1,303 @@
+use std::io::{self, Read, Write, Seek, SeekFrom};
+use std::{fmt, panic, ptr, slice, thread};
+use std::panic::AssertUnwindSafe;
+use std::ffi::{CStr, CString};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use libc::{c_char, c_int, c_void};
+use crate::sys::*;
+use crate::error::Error;
+use super::dictionary::*;
+
+const BUFFER_SIZE: usize = 32 * 1024;
+
+// A format context source or sink backed by Rust IO instead of a URL. Owns the wrapped stream, which is
+// dropped with the context
+pub struct IoContext {
+	ptr: *mut AVIOContext,
+	opaque: *mut c_void,
+	drop_opaque: unsafe fn(*mut c_void),
+	writable: bool,
+}
+
+// The constructors only accept `Send` streams and the AVIOContext is owned exclusively
+unsafe impl Send for IoContext {}
+
+impl IoContext {
+	// Reads `reader` front to back, demuxers that need to seek such as MP4 with a trailing index fail
+	pub fn reader<R: Read + Send + 'static>(reader: R) -> Result<Self, Error> {
+		Self::create(Box::new(reader), false, Some(read_packet::<R>), None, None)
+	}
+
+	pub fn seekable_reader<R: Read + Seek + Send + 'static>(reader: R) -> Result<Self, Error> {
+		Self::create(Box::new(reader), false, Some(read_packet::<R>), None, Some(seek::<R>))
+	}
+
+	// Muxers that rewrite their header on finishing, such as WAV, leave it incomplete
+	pub fn writer<W: Write + Send + 'static>(writer: W) -> Result<Self, Error> {
+		Self::create(Box::new(writer), true, None, Some(write_packet::<W>), None)
+	}
+
+	pub fn seekable_writer<W: Write + Seek + Send + 'static>(writer: W) -> Result<Self, Error> {
+		Self::create(Box::new(writer), true, None, Some(write_packet::<W>), Some(seek::<W>))
+	}
+
+	fn create<T>(
+		stream: Box<T>,
+		writable: bool,
+		read: Option<unsafe extern "C" fn(*mut c_void, *mut u8, c_int) -> c_int>,
+		write: Option<unsafe extern "C" fn(*mut c_void, *mut u8, c_int) -> c_int>,
+		seek: Option<unsafe extern "C" fn(*mut c_void, i64, c_int) -> i64>,
+	) -> Result<Self, Error> {
+		let opaque = Box::into_raw(stream) as *mut c_void;
+		unsafe {
+			let buffer = av_malloc(BUFFER_SIZE) as *mut u8;
+			if buffer.is_null() {
+				drop_box::<T>(opaque);
+				return Err(Error::from("Could not allocate the io buffer"));
+			}
+			let ptr = avio_alloc_context(buffer, BUFFER_SIZE as c_int, writable as c_int, opaque, read, write, seek);
+			if ptr.is_null() {
+				av_free(buffer as *mut c_void);
+				drop_box::<T>(opaque);
+				return Err(Error::from("Could not allocate the io context"));
+			}
+			Ok(IoContext { ptr, opaque, drop_opaque: drop_box::<T>, writable })
+		}
+	}
+
+	pub fn as_ptr(&self) -> *const AVIOContext { self.ptr }
+
+	pub fn as_mut_ptr(&mut self) -> *mut AVIOContext { self.ptr }
+
+	pub fn is_seekable(&self) -> bool { unsafe { (*self.ptr).seekable != 0 } }
+}
+
+impl Drop for IoContext {
+	fn drop(&mut self) {
+		unsafe {
+			if self.writable {
+				avio_flush(self.ptr);
+			}
+			// The buffer may have been replaced by the demuxer, so free the current one
+			av_freep(&mut (*self.ptr).buffer as *mut *mut u8 as *mut c_void);
+			avio_context_free(&mut self.ptr);
+			(self.drop_opaque)(self.opaque);
+		}
+	}
+}
+
+unsafe fn drop_box<T>(opaque: *mut c_void) {
+	drop(Box::from_raw(opaque as *mut T));
+}
+
+// Panics of the wrapped stream must not unwind into libavformat, they fail the IO call instead
+fn guard<T, F: FnOnce() -> T>(error: T, f: F) -> T {
+	panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(error)
+}
+
+unsafe extern "C" fn read_packet<R: Read>(opaque: *mut c_void, buf: *mut u8, size: c_int) -> c_int {
+	guard(-libc::EIO, || {
+		let reader = &mut *(opaque as *mut R);
+		let buf = slice::from_raw_parts_mut(buf, size as usize);
+		match retry(|| reader.read(buf)) {
+			Ok(0) => AVERROR_EOF,
+			Ok(n) => n as c_int,
+			Err(_) => -libc::EIO,
+		}
+	})
+}
+
+unsafe extern "C" fn write_packet<W: Write>(opaque: *mut c_void, buf: *mut u8, size: c_int) -> c_int {
+	guard(-libc::EIO, || {
+		let writer = &mut *(opaque as *mut W);
+		// Retries interrupted writes
+		match writer.write_all(slice::from_raw_parts(buf, size as usize)) {
+			Ok(()) => size,
+			Err(_) => -libc::EIO,
+		}
+	})
+}
+
+unsafe extern "C" fn seek<S: Seek>(opaque: *mut c_void, offset: i64, whence: c_int) -> i64 {
+	guard(-libc::EIO as i64, || {
+		let stream = &mut *(opaque as *mut S);
+		let whence = whence & !(AVSEEK_FORCE as c_int);
+		if whence == AVSEEK_SIZE as c_int {
+			// The size is found by seeking to the end and back
+			let position = match retry(|| stream.seek(SeekFrom::Current(0))) {
+				Ok(position) => position,
+				Err(_) => return -libc::EIO as i64,
+			};
+			let size = retry(|| stream.seek(SeekFrom::End(0)));
+			return match retry(|| stream.seek(SeekFrom::Start(position))).and(size) {
+				Ok(size) => size as i64,
+				Err(_) => -libc::EIO as i64,
+			};
+		}
+
+		let position = match whence {
+			libc::SEEK_SET => SeekFrom::Start(offset as u64),
+			libc::SEEK_CUR => SeekFrom::Current(offset),
+			libc::SEEK_END => SeekFrom::End(offset),
+			_ => return -libc::EINVAL as i64,
+		};
+		match retry(|| stream.seek(position)) {
+			Ok(position) => position as i64,
+			Err(_) => -libc::EIO as i64,
+		}
+	})
+}
+
+fn retry<T, F: FnMut() -> io::Result<T>>(mut f: F) -> io::Result<T> {
+	loop {
+		match f() {
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+			result => return result,
+		}
+	}
+}
+
+// Invoked after every write with the bytes written by it and in total
+pub type WriteCallback = Arc<dyn Fn(usize, u64) + Send + Sync>;
+
+// How `Throttle` paces and reports the writes
+#[derive(Clone, Default)]
+pub struct Pacing {
+	pub bytes_per_second: Option<f64>,
+	pub on_write: Option<WriteCallback>,
+}
+
+impl fmt::Debug for Pacing {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Pacing")
+			.field("bytes_per_second", &self.bytes_per_second)
+			.field("on_write", &self.on_write.is_some())
+			.finish()
+	}
+}
+
+// Holds back writes so no more than `bytes_per_second` have been written since the first one, for sinks
+// that expect the data at the play-out rate instead of in bursts
+pub struct Throttle<W> {
+	inner: W,
+	pacing: Pacing,
+	start: Option<Instant>,
+	written: u64,
+}
+
+impl<W> Throttle<W> {
+	pub fn new(inner: W, pacing: Pacing) -> Self {
+		Throttle { inner, pacing, start: None, written: 0 }
+	}
+
+	pub fn written(&self) -> u64 { self.written }
+}
+
+impl<W: Write> Write for Throttle<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let start = *self.start.get_or_insert_with(Instant::now);
+		let count = self.inner.write(buf)?;
+		self.written += count as u64;
+		// Runs inside the IO callback, where a panic fails the write rather than unwinding into FFmpeg
+		if let Some(ref callback) = self.pacing.on_write {
+			callback(count, self.written);
+		}
+
+		if let Some(rate) = self.pacing.bytes_per_second.filter(|r| *r > 0.) {
+			let due = Duration::from_secs_f64(self.written as f64 / rate);
+			let elapsed = start.elapsed();
+			if due > elapsed {
+				thread::sleep(due - elapsed);
+			}
+		}
+		Ok(count)
+	}
+
+	fn flush(&mut self) -> io::Result<()> { self.inner.flush() }
+}
+
+impl<W: Seek> Seek for Throttle<W> {
+	fn seek(&mut self, position: SeekFrom) -> io::Result<u64> { self.inner.seek(position) }
+}
+
+// Writes to a URL through the FFmpeg protocols, so custom IO can sit in front of files, TCP or RTMP
+pub struct UrlWriter {
+	ptr: *mut AVIOContext,
+}
+
+unsafe impl Send for UrlWriter {}
+
+impl UrlWriter {
+	pub fn open(url: &str, options: &mut Dictionary) -> Result<Self, Error> {
+		let curl = CString::new(url).map_err(|_| Error::from(format!("Invalid url: {}", url)))?;
+		let mut ptr = ptr::null_mut();
+		unsafe {
+			ffm_op!(avio_open2(&mut ptr, curl.as_ptr(), url_write_flags(url), ptr::null(), options.as_mut_ptr_ref()))?;
+		}
+		Ok(UrlWriter { ptr })
+	}
+
+	pub fn is_seekable(&self) -> bool { unsafe { (*self.ptr).seekable != 0 } }
+}
+
+impl Write for UrlWriter {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		unsafe {
+			avio_write(self.ptr, buf.as_ptr(), buf.len() as c_int);
+			match (*self.ptr).error {
+				0 => Ok(buf.len()),
+				e => Err(io_error(e)),
+			}
+		}
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		unsafe { avio_flush(self.ptr) };
+		Ok(())
+	}
+}
+
+impl Seek for UrlWriter {
+	fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+		let (offset, whence) = match position {
+			SeekFrom::Start(offset) => (offset as i64, libc::SEEK_SET),
+			SeekFrom::Current(offset) => (offset, libc::SEEK_CUR),
+			SeekFrom::End(offset) => (offset, libc::SEEK_END),
+		};
+		match unsafe { avio_seek(self.ptr, offset, whence) } {
+			e if e < 0 => Err(io_error(e as c_int)),
+			position => Ok(position as u64),
+		}
+	}
+}
+
+// AVERROR codes wrap errno values below the tagged errors such as AVERROR_EOF, which have no OS error
+fn io_error(code: c_int) -> io::Error {
+	match AVUNERROR(code) {
+		errno if errno > 0 && errno < 0x10000 => io::Error::from_raw_os_error(errno),
+		_ => {
+			let mut buf = [0 as c_char; AV_ERROR_MAX_STRING_SIZE as usize];
+			let message = unsafe {
+				av_strerror(code, buf.as_mut_ptr(), AV_ERROR_MAX_STRING_SIZE);
+				CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned()
+			};
+			io::Error::new(io::ErrorKind::Other, message)
+		}
+	}
+}
+
+// Network sinks can't be opened for reading
+pub(crate) fn url_write_flags(url: &str) -> c_int {
+	let flags = match url.contains("://") && !url.starts_with("file:") {
+		true => AVIO_FLAG_WRITE,
+		false => AVIO_FLAG_READ_WRITE
+	};
+	flags as c_int
+}
+
+impl Drop for UrlWriter {
+	fn drop(&mut self) {
+		unsafe { avio_closep(&mut self.ptr) };
+	}
+}
@@ -0,0 +1,157 @@
+use std::fmt;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::{Arc, Mutex, Once};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::ffi::CStr;
+use libc::{c_char, c_int, c_void};
+use crate::sys::*;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum LogLevel {
+	Quiet = -8,
+	Panic = 0,
+	Fatal = 8,
+	Error = 16,
+	Warning = 24,
+	Info = 32,
+	Verbose = 40,
+	Debug = 48,
+	Trace = 56,
+}
+
+impl LogLevel {
+	fn from_ff(level: c_int) -> Self {
+		match level {
+			l if l < 0 => LogLevel::Quiet,
+			l if l < 8 => LogLevel::Panic,
+			l if l < 16 => LogLevel::Fatal,
+			l if l < 24 => LogLevel::Error,
+			l if l < 32 => LogLevel::Warning,
+			l if l < 40 => LogLevel::Info,
+			l if l < 48 => LogLevel::Verbose,
+			l if l < 56 => LogLevel::Debug,
+			_ => LogLevel::Trace,
+		}
+	}
+}
+
+// The va_list argument of av_log_set_callback as ffmpeg-sys declares it, the x86_64 array type decays to a pointer
+#[cfg(all(target_arch = "x86_64", not(windows)))]
+pub type VaList = *mut __va_list_tag;
+#[cfg(not(all(target_arch = "x86_64", not(windows))))]
+pub type VaList = va_list;
+
+pub type LogCallback = unsafe extern "C" fn(*mut c_void, c_int, *const c_char, VaList);
+
+static FALLBACK: AtomicUsize = AtomicUsize::new(0);
+
+// FFmpeg has a single global callback without a getter, so one installed by the application is replaced
+// once a scope is created. Passing it here forwards the messages of contexts without a scope to it instead
+pub fn set_fallback_callback(callback: Option<LogCallback>) {
+	FALLBACK.store(callback.map_or(0, |c| c as usize), Ordering::Release);
+}
+
+fn fallback_callback() -> LogCallback {
+	match FALLBACK.load(Ordering::Acquire) {
+		0 => av_log_default_callback,
+		callback => unsafe { mem::transmute::<usize, LogCallback>(callback) }
+	}
+}
+
+// Messages of contexts opened with this config up to `level`, passed to `callback` or else printed to stderr
+#[derive(Clone)]
+pub struct LogConfig {
+	pub level: LogLevel,
+	pub callback: Option<Arc<dyn Fn(LogLevel, &str) + Send + Sync>>,
+}
+
+impl fmt::Debug for LogConfig {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("LogConfig").field("level", &self.level).field("callback", &self.callback.is_some()).finish()
+	}
+}
+
+// Applies a config to the attached contexts until dropped, other contexts keep the global FFmpeg level
+pub struct LogScope {
+	id: usize,
+	config: LogConfig,
+}
+
+impl LogScope {
+	pub fn new(config: &LogConfig) -> Self {
+		static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+		install_callback();
+		LogScope { id: NEXT_ID.fetch_add(1, Ordering::Relaxed), config: config.clone() }
+	}
+
+	// `ctx` has to start with an AVClass pointer, like the format and codec contexts
+	pub fn attach(&mut self, ctx: *const c_void) {
+		registry().lock().unwrap_or_else(|e| e.into_inner()).push((ctx as usize, self.id, self.config.clone()));
+	}
+}
+
+impl Drop for LogScope {
+	fn drop(&mut self) {
+		registry().lock().unwrap_or_else(|e| e.into_inner()).retain(|(_, id, _)| *id != self.id);
+	}
+}
+
+// Context address, scope id and config, the last scope attaching a context wins
+type Registry = Mutex<Vec<(usize, usize, LogConfig)>>;
+
+fn registry() -> &'static Registry {
+	static INIT: Once = Once::new();
+	static mut REGISTRY: *const Registry = ptr::null();
+	unsafe {
+		INIT.call_once(|| REGISTRY = Box::into_raw(Box::new(Mutex::new(Vec::new()))));
+		&*REGISTRY
+	}
+}
+
+fn install_callback() {
+	static INSTALL: Once = Once::new();
+	INSTALL.call_once(|| unsafe { av_log_set_callback(Some(log_callback)) });
+}
+
+// Config of `ctx` or of the context it belongs to, e.g. the format context of its AVIOContext. A poisoned
+// registry sends the messages to the fallback callback rather than panicking inside FFmpeg
+unsafe fn find_config(mut ctx: *mut c_void) -> Option<LogConfig> {
+	let registry = registry().lock().ok()?;
+	for _ in 0..4 {
+		if ctx.is_null() {
+			return None;
+		}
+		if let Some((_, _, config)) = registry.iter().rev().find(|(c, _, _)| *c == ctx as usize) {
+			return Some(config.clone());
+		}
+
+		let class = *(ctx as *const *const AVClass);
+		if class.is_null() || (*class).parent_log_context_offset == 0 {
+			return None;
+		}
+		ctx = *((ctx as *const u8).offset((*class).parent_log_context_offset as isize) as *const *mut c_void);
+	}
+	None
+}
+
+unsafe extern "C" fn log_callback(ctx: *mut c_void, level: c_int, fmt: *const c_char, args: VaList) {
+	let config = match find_config(ctx) {
+		None => return fallback_callback()(ctx, level, fmt, args),
+		Some(config) => config
+	};
+	if level > config.level as c_int {
+		return;
+	}
+
+	let mut line = [0 as c_char; 1024];
+	let mut print_prefix = 1;
+	av_log_format_line(ctx, level, fmt, args, line.as_mut_ptr(), line.len() as c_int, &mut print_prefix);
+	let line = CStr::from_ptr(line.as_ptr()).to_string_lossy();
+	// Panics must not unwind into FFmpeg
+	let _ = panic::catch_unwind(AssertUnwindSafe(|| match config.callback {
+		Some(ref callback) => callback(LogLevel::from_ff(level), line.trim_end()),
+		None => eprint!("{}", line),
+	}));
+}
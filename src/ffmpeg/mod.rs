@@ -3,13 +3,25 @@ pub mod format;
 pub mod codec;
 pub mod containers;
 pub mod format_context;
+pub mod io_context;
 pub mod stream;
 pub mod converter;
+pub mod filter_graph;
+pub mod dictionary;
+pub mod log;
+pub mod rational;
+pub mod bitstream_filter;
 
 pub use utils::*;
 pub use format::*;
 pub use codec::*;
 pub use containers::*;
 pub use format_context::*;
+pub use io_context::*;
 pub use stream::*;
 pub use converter::*;
+pub use filter_graph::*;
+pub use dictionary::*;
+pub use log::*;
+pub use rational::*;
+pub use bitstream_filter::*;
@@ -0,0 +1,51 @@
+use std::fmt;
+use std::time::Duration;
+use crate::sys::*;
+
+// Time base of streams and codecs, a timestamp `ts` is `ts * num / den` seconds
+#[derive(new, Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Rational {
+	pub num: i32,
+	pub den: i32,
+}
+
+impl Rational {
+	// The microsecond time base of container durations
+	pub const MICROS: Rational = Rational { num: 1, den: AV_TIME_BASE as i32 };
+
+	// Time base counting samples at `sample_rate`
+	pub fn samples(sample_rate: i32) -> Self { Rational { num: 1, den: sample_rate } }
+
+	pub fn to_f64(&self) -> f64 { self.num as f64 / self.den as f64 }
+
+	// Converts `ts` to the time base `to`, rounding to the nearest
+	pub fn rescale(&self, ts: i64, to: Rational) -> i64 {
+		unsafe { av_rescale_q(ts, (*self).into(), to.into()) }
+	}
+
+	// None for negative timestamps and AV_NOPTS_VALUE
+	pub fn to_duration(&self, ts: i64) -> Option<Duration> {
+		match ts < 0 {
+			true => None,
+			false => Some(Duration::from_micros(self.rescale(ts, Rational::MICROS) as u64))
+		}
+	}
+
+	pub fn from_duration(&self, duration: Duration) -> i64 {
+		Rational::MICROS.rescale(duration.as_micros() as i64, *self)
+	}
+}
+
+impl From<AVRational> for Rational {
+	fn from(r: AVRational) -> Self { Rational { num: r.num, den: r.den } }
+}
+
+impl From<Rational> for AVRational {
+	fn from(r: Rational) -> Self { AVRational { num: r.num, den: r.den } }
+}
+
+impl fmt::Display for Rational {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}/{}", self.num, self.den)
+	}
+}
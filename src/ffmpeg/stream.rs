@@ -2,8 +2,12 @@ use sys::*;
 use super::codec::codec_params::*;
 use super::utils::*;
 use super::format_context::*;
+use super::dictionary::*;
+use super::rational::*;
 use crate::error::Error;
 use std::ptr;
+use std::slice;
+use std::time::Duration;
 
 ff_wrap_struct!(Stream, AVStream);
 ff_wrap!(Stream, AVStream);
@@ -19,4 +23,84 @@ impl Stream {
 	}
 
 	pub fn id(&self) -> i32 { self.as_ref().id }
+
+	pub fn index(&self) -> i32 { self.as_ref().index }
+
+	pub fn time_base(&self) -> Rational { Rational::from(self.as_ref().time_base) }
+
+	pub fn set_time_base(&mut self, time_base: Rational) {
+		self.as_mut_ref().time_base = time_base.into();
+	}
+
+	// None when the container doesn't store the stream duration
+	pub fn duration(&self) -> Option<Duration> { self.duration_ts().and_then(|ts| self.time_base().to_duration(ts)) }
+
+	// Duration in the stream time base
+	pub fn duration_ts(&self) -> Option<i64> {
+		match self.as_ref().duration {
+			d if d <= 0 => None,
+			d => Some(d)
+		}
+	}
+
+	// Packet count stored by the container, None when unknown
+	pub fn nb_frames(&self) -> Option<i64> {
+		match self.as_ref().nb_frames {
+			0 => None,
+			n => Some(n)
+		}
+	}
+
+	pub fn copy_metadata_from(&mut self, other: &Stream) -> Result<(), Error> {
+		unsafe { ffm_ret!(av_dict_copy(&mut self.as_mut_ref().metadata, other.as_ref().metadata, 0))?; }
+		self.as_mut_ref().disposition = other.as_ref().disposition;
+		Ok(())
+	}
+
+	pub fn disposition(&self) -> i32 { self.as_ref().disposition }
+
+	pub fn is_default(&self) -> bool { self.disposition() & AV_DISPOSITION_DEFAULT as i32 != 0 }
+
+	// Embedded cover art, exposed by demuxers as a video stream holding a single packet
+	pub fn is_attached_picture(&self) -> bool { self.disposition() & AV_DISPOSITION_ATTACHED_PIC as i32 != 0 }
+
+	// Encoded image of an attached picture stream
+	pub fn attached_picture(&self) -> Option<&[u8]> {
+		let packet = &self.as_ref().attached_pic;
+		match self.is_attached_picture() && !packet.data.is_null() {
+			true => Some(unsafe { slice::from_raw_parts(packet.data, packet.size as usize) }),
+			false => None
+		}
+	}
+
+	// Asks the demuxer to skip the packets of this stream
+	pub fn set_discard(&mut self, discard: bool) {
+		self.as_mut_ref().discard = match discard {
+			true => AVDiscard::AVDISCARD_ALL,
+			false => AVDiscard::AVDISCARD_DEFAULT
+		};
+	}
+
+	// Contents of an attachment stream, such as a font or an image
+	pub fn attachment(&self) -> Option<&[u8]> {
+		let params = self.as_ref().codecpar;
+		unsafe {
+			match (*params).codec_type == AVMediaType::AVMEDIA_TYPE_ATTACHMENT && !(*params).extradata.is_null() {
+				true => Some(slice::from_raw_parts((*params).extradata, (*params).extradata_size as usize)),
+				false => None
+			}
+		}
+	}
+
+	pub fn metadata(&self, key: &str) -> Option<String> {
+		unsafe { dict_get(self.as_ref().metadata, key) }
+	}
+
+	pub fn metadata_entries(&self) -> Vec<(String, String)> {
+		unsafe { dict_entries(self.as_ref().metadata) }
+	}
+
+	pub fn set_metadata(&mut self, key: &str, value: &str) -> Result<(), Error> {
+		unsafe { dict_set(&mut self.as_mut_ref().metadata, key, value) }
+	}
 }
\ No newline at end of file
@@ -63,3 +63,9 @@ macro_rules! wrap_ff_wrap (
 	}
 );
 
+
+macro_rules! cstr (
+	($s: expr) => {
+		concat!($s, "\0").as_ptr() as *const libc::c_char
+	}
+);
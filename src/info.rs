@@ -0,0 +1,263 @@
+use std::fmt;
+use std::ffi::CStr;
+use std::str::from_utf8_unchecked;
+use std::time::Duration;
+use crate::sys::*;
+use crate::ffmpeg::*;
+use crate::error::Error;
+use crate::metadata::Metadata;
+
+// Upper bound when counting subsongs
+const MAX_SUBSONGS: usize = 256;
+
+// Codecs of old voicemail and IVR archives, raw or wrapped in WAV
+const TELEPHONY_CODECS: [AVCodecID; 5] = [
+	AVCodecID::AV_CODEC_ID_ADPCM_IMA_WAV,
+	AVCodecID::AV_CODEC_ID_ADPCM_MS,
+	AVCodecID::AV_CODEC_ID_GSM,
+	AVCodecID::AV_CODEC_ID_GSM_MS,
+	AVCodecID::AV_CODEC_ID_SPEEX,
+];
+
+#[derive(Clone, Debug)]
+pub struct AudioInfo {
+	pub codec_name: String,
+	pub profile: Option<String>,
+	pub bit_rate: Option<i64>,
+	pub bits_per_sample: Option<i32>,
+	pub lossless: bool,
+	pub sample_rate: i32,
+	pub channels: i32,
+	pub dsd_rate: Option<i32>,
+	// None for live streams and pipes
+	pub duration: Option<Duration>,
+}
+
+impl AudioInfo {
+	pub fn from_stream(stream: &Stream) -> Self {
+		let params = stream.parameters();
+		// DSD demuxers report the byte rate, which is also the rate the decoders produce PCM at
+		let dsd_rate = match params.is_dsd() {
+			true => Some(params.sample_rate() * 8),
+			false => None
+		};
+
+		AudioInfo {
+			codec_name: params.codec_name().to_string(),
+			profile: params.profile_name().map(|p| p.to_string()),
+			bit_rate: params.bit_rate(),
+			bits_per_sample: params.bits_per_sample(),
+			lossless: params.is_lossless(),
+			sample_rate: params.sample_rate(),
+			channels: params.channels(),
+			dsd_rate,
+			duration: stream.duration(),
+		}
+	}
+
+	pub fn is_dsd(&self) -> bool { self.dsd_rate.is_some() }
+
+	pub fn has_known_duration(&self) -> bool { self.duration.is_some() }
+}
+
+// Short classification such as "FLAC 24/96" for lossless and "AAC-LC 256k" for lossy streams
+impl fmt::Display for AudioInfo {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.codec_name.to_uppercase())?;
+		if let Some(ref profile) = self.profile {
+			write!(f, "-{}", profile)?;
+		}
+		match (self.lossless, self.bits_per_sample, self.bit_rate) {
+			(true, Some(bits), _) => write!(f, " {}/{}", bits, self.sample_rate as f64 / 1000.),
+			(_, _, Some(bit_rate)) => write!(f, " {}k", bit_rate / 1000),
+			_ => Ok(())
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct StreamInfo {
+	pub index: i32,
+	// The PID in MPEG-TS
+	pub id: i32,
+	pub language: Option<String>,
+	pub title: Option<String>,
+	// Marked as the default stream
+	pub default: bool,
+	pub audio: AudioInfo,
+}
+
+impl StreamInfo {
+	pub fn from_stream(stream: &Stream) -> Self {
+		StreamInfo {
+			index: stream.index(),
+			id: stream.id(),
+			language: stream.metadata("language"),
+			title: stream.metadata("title"),
+			default: stream.is_default(),
+			audio: AudioInfo::from_stream(stream),
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct CoverArt {
+	// Encoded image
+	pub data: Vec<u8>,
+	// Image codec, usually mjpeg or png
+	pub codec_name: String,
+}
+
+impl CoverArt {
+	pub fn from_stream(stream: &Stream) -> Option<Self> {
+		stream.attached_picture().map(|data| CoverArt {
+			data: data.to_vec(),
+			codec_name: stream.parameters().codec_name().to_string(),
+		})
+	}
+}
+
+// Info of the audio stream `Reader` would decode, read from the container without opening the decoder.
+// Durations and bit rates missing from the stream are taken from the container
+pub fn probe(path: &str) -> Result<AudioInfo, Error> {
+	let format_ctx = open_read(path)?;
+	let stream = format_ctx.get_audio_stream().ok_or("Could not find any audio stream in the file")?;
+	let info = AudioInfo::from_stream(&stream);
+	Ok(AudioInfo {
+		duration: info.duration.or(format_ctx.duration()),
+		bit_rate: info.bit_rate.or(format_ctx.bit_rate()),
+		..info
+	})
+}
+
+// Tags of the file and of the audio stream `Reader` would decode
+pub fn metadata(path: &str) -> Result<Metadata, Error> {
+	let format_ctx = open_read(path)?;
+	let stream = format_ctx.get_audio_stream().ok_or("Could not find any audio stream in the file")?;
+	Ok(Metadata::from_input(&format_ctx, &stream))
+}
+
+// Pictures embedded in the file, such as the front cover of an MP3, FLAC or M4A
+pub fn cover_art(path: &str) -> Result<Vec<CoverArt>, Error> {
+	Ok(open_read(path)?.streams().iter().filter_map(CoverArt::from_stream).collect())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChapterInfo {
+	pub start: Duration,
+	pub end: Duration,
+	pub title: Option<String>,
+}
+
+impl ChapterInfo {
+	pub fn new(start: Duration, end: Duration, title: &str) -> Self {
+		ChapterInfo { start, end, title: Some(title.to_string()) }
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attachment {
+	pub filename: String,
+	pub mime_type: String,
+	pub data: Vec<u8>,
+}
+
+impl Attachment {
+	pub fn new(filename: &str, mime_type: &str, data: Vec<u8>) -> Self {
+		Attachment { filename: filename.to_string(), mime_type: mime_type.to_string(), data }
+	}
+
+	pub fn from_stream(stream: &Stream) -> Option<Self> {
+		stream.attachment().map(|data| Attachment {
+			filename: stream.metadata("filename").unwrap_or_default(),
+			mime_type: stream.metadata("mimetype").unwrap_or_default(),
+			data: data.to_vec(),
+		})
+	}
+}
+
+// Every audio track with its language, such as the dubs of a Matroska file
+pub fn audio_streams(path: &str) -> Result<Vec<StreamInfo>, Error> {
+	Ok(open_read(path)?.audio_streams(None).iter().map(StreamInfo::from_stream).collect())
+}
+
+pub fn chapters(path: &str) -> Result<Vec<ChapterInfo>, Error> {
+	Ok(open_read(path)?.chapters().iter().map(|c| ChapterInfo { start: c.start(), end: c.end(), title: c.title() }).collect())
+}
+
+// Files stored in the container, such as the fonts and images of a Matroska file
+pub fn attachments(path: &str) -> Result<Vec<Attachment>, Error> {
+	Ok(open_read(path)?.streams().iter().filter_map(Attachment::from_stream).collect())
+}
+
+// Songs selectable with `ReaderOptions::subsong`, 1 for inputs without subsongs. The demuxers don't
+// report the count, so songs are opened in turn until one is rejected
+pub fn subsongs(path: &str) -> Result<usize, Error> {
+	let format = open_read(path)?.input_format_name().unwrap_or_default();
+	if format != "libgme" && format != "libopenmpt" {
+		return Ok(1);
+	}
+
+	let mut count = 1;
+	while count < MAX_SUBSONGS {
+		let options = vec![
+			("track_index".to_string(), count.to_string()),
+			("subsong".to_string(), count.to_string()),
+		];
+		if open_read_with(path, Some(&format), &mut Dictionary::from_pairs(&options)?).is_err() {
+			break;
+		}
+		count += 1;
+	}
+	Ok(count)
+}
+
+#[derive(Clone, Debug)]
+pub struct ProgramInfo {
+	pub id: i32,
+	pub number: i32,
+	pub name: Option<String>,
+	pub audio_streams: Vec<StreamInfo>,
+}
+
+// Programs of a multi-program input such as an MPEG transport stream, empty for most formats
+pub fn programs(path: &str) -> Result<Vec<ProgramInfo>, Error> {
+	let format_ctx = open_read(path)?;
+	let streams = format_ctx.streams();
+
+	Ok(format_ctx.programs().iter().map(|program| {
+		let indices = program.stream_indices();
+		ProgramInfo {
+			id: program.id(),
+			number: program.number(),
+			name: program.metadata("service_name"),
+			audio_streams: streams.iter()
+				.filter(|s| indices.contains(&s.index()) && s.parameters().is_audio())
+				.map(StreamInfo::from_stream)
+				.collect(),
+		}
+	}).collect())
+}
+
+#[derive(Clone, Debug)]
+pub struct CodecSupport {
+	pub codec_name: &'static str,
+	// The decoder FFmpeg picks, None if the build has none
+	pub decoder: Option<&'static str>,
+}
+
+impl CodecSupport {
+	pub fn of(id: AVCodecID) -> Self {
+		CodecSupport {
+			codec_name: unsafe { from_utf8_unchecked(CStr::from_ptr(avcodec_get_name(id)).to_bytes()) },
+			decoder: Codec::decoder(id).map(|c| c.name()),
+		}
+	}
+
+	pub fn is_supported(&self) -> bool { self.decoder.is_some() }
+}
+
+// Decoding support of IMA and MS ADPCM, GSM 6.10 and Speex. Speex needs a build with libspeex before FFmpeg 4.4
+pub fn telephony_support() -> Vec<CodecSupport> {
+	TELEPHONY_CODECS.iter().map(|id| CodecSupport::of(*id)).collect()
+}
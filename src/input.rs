@@ -1,22 +1,110 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{Cursor, Read, Seek};
+use std::time::Duration;
+use libc::c_void;
 use crate::sys::*;
 use crate::ffmpeg::*;
 use crate::error::Error;
+use crate::info::{AudioInfo, CoverArt, StreamInfo};
+use crate::options::{ReaderOptions, StreamPolicy, StreamCriterion};
+use crate::timecode::Timecode;
+use crate::metadata::Metadata;
+use crate::time::*;
 
 pub struct Input {
 	format_ctx: FormatContext,
 	stream: Stream,
 	codec_ctx: DecoderOpen,
+	_log: Option<LogScope>,
 }
 
 impl Input {
 	pub fn open<F>(path: &str, format_picker: F) -> Result<Self, Error>
 		where F: Fn(FormatIter) -> Option<SampleFormat>
 	{
-		let format_ctx = open_read(path)?;
-		let stream = format_ctx.get_audio_stream()
-			.ok_or("Could not find any audio stream in the file")?;
+		Self::from_format_ctx(open_read(path)?, format_picker)
+	}
+
+	// `path` may also be a URL such as `https://...` or `rtmp://...`, the demuxer and protocol options of
+	// `options` (user agent, headers, reconnecting, timeouts) are passed when opening it
+	pub fn open_with<F>(path: &str, options: &ReaderOptions, format_picker: F) -> Result<Self, Error>
+		where F: Fn(FormatIter) -> Option<SampleFormat>
+	{
+		let mut format_options = Dictionary::from_pairs(&options.format_options)?;
+		let mut log = options.log.as_ref().map(LogScope::new);
+		let format_ctx = open_read_scoped(path, options.input_format.as_ref().map(|f| f.as_str()), &mut format_options, log.as_mut())?;
+		Self::from_format_ctx_scoped(format_ctx, options, log, format_picker)
+	}
+
+	// Decodes the audio stream meeting `criterion`, such as the second track of a Matroska file or a language
+	pub fn open_stream<F>(path: &str, criterion: StreamCriterion, format_picker: F) -> Result<Self, Error>
+		where F: Fn(FormatIter) -> Option<SampleFormat>
+	{
+		Self::open_with(path, &ReaderOptions::new().stream_criterion(criterion), format_picker)
+	}
+
+	// Demuxes an already open file instead of opening a path
+	pub fn from_file<F>(file: File, format_picker: F) -> Result<Self, Error>
+		where F: Fn(FormatIter) -> Option<SampleFormat>
+	{
+		Self::from_reader(file, format_picker)
+	}
+
+	// Demuxes from any seekable source, such as a download kept in memory
+	pub fn from_reader<R, F>(reader: R, format_picker: F) -> Result<Self, Error>
+		where R: Read + Seek + Send + 'static, F: Fn(FormatIter) -> Option<SampleFormat>
+	{
+		let format_ctx = open_read_io(IoContext::seekable_reader(reader)?, None, &mut Dictionary::new(), None)?;
+		Self::from_format_ctx(format_ctx, format_picker)
+	}
+
+	// The data is copied, `from_reader` with a `Cursor` takes an owned buffer as is
+	pub fn from_slice<F>(data: &[u8], format_picker: F) -> Result<Self, Error>
+		where F: Fn(FormatIter) -> Option<SampleFormat>
+	{
+		Self::from_reader(Cursor::new(data.to_vec()), format_picker)
+	}
+
+	pub fn from_format_ctx<F>(format_ctx: FormatContext, format_picker: F) -> Result<Self, Error>
+		where F: Fn(FormatIter) -> Option<SampleFormat>
+	{
+		Self::from_format_ctx_with(format_ctx, &ReaderOptions::new(), format_picker)
+	}
+
+	pub fn from_format_ctx_with<F>(format_ctx: FormatContext, options: &ReaderOptions, format_picker: F) -> Result<Self, Error>
+		where F: Fn(FormatIter) -> Option<SampleFormat>
+	{
+		Self::from_format_ctx_scoped(format_ctx, options, options.log.as_ref().map(LogScope::new), format_picker)
+	}
+
+	// Keeps the scope the format context was opened in, so probing and decoding log alike
+	fn from_format_ctx_scoped<F>(
+		format_ctx: FormatContext, options: &ReaderOptions, mut log: Option<LogScope>, format_picker: F
+	) -> Result<Self, Error>
+		where F: Fn(FormatIter) -> Option<SampleFormat>
+	{
+		let stream = match (options.stream_policy, options.stream_id, options.stream_criteria.is_empty()) {
+			(StreamPolicy::First, _, true) | (_, Some(_), _) => format_ctx.find_audio_stream(options.program, options.stream_id),
+			(policy, None, _) => {
+				let streams = format_ctx.audio_streams(options.program).into_iter()
+					.filter(|s| options.matches_stream(&StreamInfo::from_stream(s)))
+					.collect();
+				select_stream(streams, policy)?
+			}
+		}.ok_or("Could not find any audio stream in the file")?;
+		// Cover art and the other streams would only be demuxed to be dropped
+		for mut other in format_ctx.streams() {
+			other.set_discard(other.index() != stream.index());
+		}
+
 		let codec_params = stream.parameters();
-		let mut codec_ctx = DecoderContext::create(&codec_params)?;
+		let mut codec_ctx = DecoderContext::create(&codec_params, options.decoder.as_ref().map(|d| d.as_str()))?;
+
+		if let Some(ref mut log) = log {
+			log.attach(format_ctx.as_ptr() as *const c_void);
+			log.attach(codec_ctx.as_ptr() as *const c_void);
+		}
 
 		let format_iter = codec_ctx.ctx().codec().formats()
 			.ok_or("Could not find appropriate sample format")?;
@@ -24,9 +112,14 @@ impl Input {
 			.ok_or("Could not find appropriate sample format")?;
 
 		codec_ctx.set_request_sample_fmt(sample_format);
-		let codec_ctx = DecoderOpen::open(codec_ctx)?;
+		if let Some(channel_layout) = options.decoder_channel_layout(&codec_params) {
+			codec_ctx.set_request_channel_layout(channel_layout);
+		}
 
-		Ok(Input { format_ctx, stream, codec_ctx })
+		let mut decoder_options = Dictionary::from_pairs(&options.decoder_options(&codec_params))?;
+		let codec_ctx = DecoderOpen::open_with(codec_ctx, &mut decoder_options)?;
+
+		Ok(Input { format_ctx, stream, codec_ctx, _log: log })
 	}
 
 	pub fn format_ctx(&self) -> &FormatContext { &self.format_ctx }
@@ -35,8 +128,82 @@ impl Input {
 
 	pub fn codec_ctx(&self) -> &DecoderOpen { &self.codec_ctx }
 
-	pub fn estimated_sample_count(&self) -> usize {
-		(self.format_ctx.get_duration() * self.codec_ctx.ctx().ctx().sample_rate() as usize) / AV_TIME_BASE as usize
+	pub fn stream_title(&self) -> Option<String> {
+		let metadata = self.format_ctx.icy_metadata()?;
+		let start = metadata.find("StreamTitle='")? + "StreamTitle='".len();
+		let end = metadata[start..].find("';").map(|e| start + e).unwrap_or(metadata.len());
+		Some(metadata[start..end].to_string())
+	}
+
+	// From the container, the audio stream or a timecode track such as MOV tmcd
+	pub fn timecode(&self) -> Option<Timecode> {
+		self.format_ctx.metadata("timecode")
+			.or_else(|| self.stream.metadata("timecode"))
+			.or_else(|| self.format_ctx.streams().iter().filter_map(|s| s.metadata("timecode")).next())
+			.and_then(|t| Timecode::parse(&t))
+	}
+
+	// Samples since midnight of the first sample, from the BWF bext chunk
+	pub fn time_reference(&self) -> Option<u64> {
+		self.format_ctx.metadata("time_reference").and_then(|t| t.trim().parse().ok())
+	}
+
+	pub fn codec_id(&self) -> AVCodecID { self.stream.parameters().codec_id() }
+
+	pub fn codec_name(&self) -> &'static str { self.stream.parameters().codec_name() }
+
+	pub fn profile(&self) -> Option<&'static str> { self.stream.parameters().profile_name() }
+
+	pub fn level(&self) -> Option<i32> { self.stream.parameters().level() }
+
+	pub fn is_lossless(&self) -> bool { self.stream.parameters().is_lossless() }
+
+	pub fn cover_art(&self) -> Vec<CoverArt> {
+		self.format_ctx.streams().iter().filter_map(CoverArt::from_stream).collect()
+	}
+
+	pub fn metadata(&self) -> Metadata { Metadata::from_input(&self.format_ctx, &self.stream) }
+
+	pub fn info(&self) -> AudioInfo {
+		AudioInfo { duration: self.duration(), ..AudioInfo::from_stream(&self.stream) }
+	}
+
+	pub fn seek(&mut self, position: Duration) -> Result<(), Error> {
+		let timestamp = self.stream.time_base().from_duration(position);
+		self.format_ctx.seek(self.stream.index(), timestamp)?;
+		self.codec_ctx.flush();
+		Ok(())
+	}
+
+	// None for live streams and pipes
+	pub fn duration(&self) -> Option<Duration> { self.stream.duration().or(self.format_ctx.duration()) }
+
+	// From the stream duration in its own time base, then the container duration, then the packet count
+	// of fixed frame size codecs. None when unknown or out of range
+	pub fn estimated_sample_count(&self) -> Option<usize> {
+		let sample_rate = self.codec_ctx.ctx().ctx().sample_rate() as i128;
+		let to_samples = |ts: i64, time_base: Rational| {
+			if time_base.den <= 0 {
+				return None;
+			}
+			let samples = (ts as i128).checked_mul(time_base.num as i128)?.checked_mul(sample_rate)? / time_base.den as i128;
+			usize::try_from(samples).ok()
+		};
+
+		let params = self.stream.parameters();
+		self.stream.duration_ts().and_then(|ts| to_samples(ts, self.stream.time_base()))
+			.or_else(|| self.format_ctx.duration_ts().and_then(|ts| to_samples(ts, Rational::MICROS)))
+			.or_else(|| match (self.stream.nb_frames(), params.frame_size()) {
+				(Some(frames), frame_size) if frame_size > 0 => usize::try_from(frames.checked_mul(frame_size as i64)?).ok(),
+				_ => None
+			})
+	}
+
+	// Samples per frame of fixed frame size codecs, None when the frames vary in size
+	pub fn frame_size(&self) -> Option<usize> {
+		[self.codec_ctx.ctx().ctx().frame_size(), self.stream.parameters().frame_size()].iter()
+			.find(|s| **s > 0)
+			.map(|s| *s as usize)
 	}
 
 	pub fn channel_layout(&self) -> ChannelLayout { self.codec_ctx.ctx().ctx().channel_layout() }
@@ -45,11 +212,27 @@ impl Input {
 
 	pub fn sample_rate(&self) -> i32 { self.codec_ctx.ctx().ctx().sample_rate() }
 
+	pub fn audio_format(&self) -> AudioFormat { self.codec_ctx.ctx().ctx().audio_format() }
+
 	pub fn converter(&self, dst_fmt: AudioFormat)
 		-> Result<Converter, Error> {
-		Converter::new(
-			AudioFormat::new(self.channel_layout(), self.sample_format(), self.sample_rate()),
-			dst_fmt
-		)
+		Converter::new(self.audio_format(), dst_fmt)
 	}
-}
\ No newline at end of file
+}
+
+fn select_stream(streams: Vec<Stream>, policy: StreamPolicy) -> Result<Option<Stream>, Error> {
+	match policy {
+		StreamPolicy::Unique if streams.len() > 1 => {
+			let list: Vec<String> = streams.iter().map(|s| format!(
+				"#{} {} {}ch{}", s.index(), s.parameters().codec_name(), s.parameters().channels(),
+				s.metadata("language").map(|l| format!(" ({})", l)).unwrap_or_default()
+			)).collect();
+			Err(Error::from(format!("The input has {} audio streams: {}", streams.len(), list.join(", "))))
+		},
+		// Reversed so ties go to the earlier stream
+		StreamPolicy::Best => Ok(streams.into_iter().rev().max_by_key(|s| {
+			(s.is_default(), s.parameters().channels(), s.parameters().bit_rate().unwrap_or(0))
+		})),
+		_ => Ok(streams.into_iter().next())
+	}
+}
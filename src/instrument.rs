@@ -0,0 +1,38 @@
+// Spans and events behind the `tracing` feature, the macros expand to nothing without it
+
+#[cfg(feature = "tracing")]
+pub(crate) struct Timed {
+	span: tracing::Span,
+	start: std::time::Instant,
+}
+
+#[cfg(feature = "tracing")]
+impl Timed {
+	pub(crate) fn new(span: tracing::Span) -> Self {
+		Timed { span, start: std::time::Instant::now() }
+	}
+}
+
+// Emits the elapsed time within the span once the timed scope ends
+#[cfg(feature = "tracing")]
+impl Drop for Timed {
+	fn drop(&mut self) {
+		let elapsed_us = self.start.elapsed().as_micros() as u64;
+		tracing::event!(parent: &self.span, tracing::Level::TRACE, elapsed_us = elapsed_us);
+	}
+}
+
+// Enters a span named `$name` until the returned guard is dropped
+#[cfg(feature = "tracing")]
+macro_rules! timed_span (
+	($level: ident, $name: expr $(, $($fields: tt)*)?) => {{
+		let span = tracing::span!(tracing::Level::$level, $name $(, $($fields)*)?);
+		let timed = crate::instrument::Timed::new(span.clone());
+		(timed, span.entered())
+	}}
+);
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! timed_span (
+	($level: ident, $name: expr $(, $($fields: tt)*)?) => { () }
+);
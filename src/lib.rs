@@ -5,36 +5,114 @@ extern crate libc;
 pub extern crate ffmpeg_sys as sys;
 
 #[macro_use] pub mod error;
+#[macro_use] mod instrument;
 pub mod ffmpeg;
 
+pub mod options;
+pub mod augmentation;
+pub mod info;
 pub mod input;
 pub mod reader;
 pub mod output;
 pub mod writer;
+pub mod samples;
+pub mod analysis;
+pub mod capture;
+pub mod dataset;
+pub mod loops;
+pub mod streamed;
+pub mod assets;
+pub mod duplicates;
+pub mod edit;
+pub mod timecode;
+pub mod time;
+pub mod loudness;
+pub mod bwf;
+pub mod remux;
+pub mod demux;
+pub mod mux;
+pub mod codec;
+pub mod tracks;
+pub mod build;
+pub mod metadata;
 
 use litaudio::*;
 use reader::*;
 use writer::*;
 use error::*;
+use options::*;
 use std::path::Path;
-use litcontainers::{StorageConstructor, Container, StorageMut};
+use litcontainers::{StorageConstructor, Container, StorageMut, Dynamic};
+use ffmpeg::ChannelLayout;
+
+pub use build::build_info;
 
 pub fn read_audio<T, P, S>(path: &Path) -> Result<Container<T, AudioContainer<T, P, S>>, Error>
 	where T: Sample, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+{
+	read_audio_with(path, ReaderOptions::new())
+}
+
+pub fn read_audio_with<T, P, S>(path: &Path, options: ReaderOptions) -> Result<Container<T, AudioContainer<T, P, S>>, Error>
+	where T: Sample, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
 {
 	let path = match path.to_str() {
 		None => return Err(Error::from(format!("Invalid path: {}", path.display()))),
 		Some(s) => s
 	};
-	Reader::open(&path, None)?.read().map(|v| v.into())
+	Reader::open_with(&path, options)?.read().map(|v| v.into())
+}
+
+pub fn read_audio_resampled<T>(path: &Path, sample_rate: i32, channel_layout: ChannelLayout)
+	-> Result<AudioDeinterleaved<T, Dynamic, Dynamic>, Error>
+	where T: Sample
+{
+	read_audio_with(
+		path,
+		ReaderOptions::new()
+			.channel_count(Some(channel_layout.channels() as usize))
+			.target_sample_rate(Some(sample_rate))
+	)
+}
+
+// Interleaved samples with their channel count and sample rate, for callers not using litcontainers
+pub fn read_interleaved_vec<T>(path: &Path, options: ReaderOptions) -> Result<(Vec<T>, usize, i32), Error>
+	where T: Sample
+{
+	let audio: AudioInterleaved<T, Dynamic, Dynamic> = read_audio_with(path, options)?;
+	let (channels, sample_rate) = (audio.channels(), audio.sample_rate());
+	// The interleaved storage is already laid out the way callers expect, its buffer is handed over as is
+	Ok((audio.into_storage().into_storage().into_vec(), channels, sample_rate))
+}
+
+// One vector per channel with the sample rate, for callers not using litcontainers
+pub fn read_planar_vecs<T>(path: &Path, options: ReaderOptions) -> Result<(Vec<Vec<T>>, i32), Error>
+	where T: Sample
+{
+	let audio: AudioDeinterleaved<T, Dynamic, Dynamic> = read_audio_with(path, options)?;
+	let (channel_count, samples, sample_rate) = (audio.channels(), audio.samples(), audio.sample_rate());
+	// Channels are contiguous, so the first channel keeps the storage buffer and only the others are split off
+	let mut data = audio.into_storage().into_storage().into_vec();
+	let mut channels: Vec<Vec<T>> = (1..channel_count).rev().map(|c| data.split_off(c * samples)).collect();
+	if channel_count > 0 {
+		channels.push(data);
+	}
+	channels.reverse();
+	Ok((channels, sample_rate))
 }
 
 pub fn write_audio<S, T, P>(path: &Path, audio: &S) -> Result<(), Error>
 	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P>
+{
+	write_audio_with(path, audio, WriterOptions::new())
+}
+
+pub fn write_audio_with<S, T, P>(path: &Path, audio: &S, options: WriterOptions) -> Result<(), Error>
+	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P>
 {
 	let path = match path.to_str() {
 		None => return Err(Error::from(format!("Invalid path: {}", path.display()))),
 		Some(s) => s
 	};
-	Writer::open(&path, audio)?.write()
+	Writer::open_with(&path, audio, options)?.write()
 }
@@ -0,0 +1,62 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use crate::error::Error;
+
+// Sample frame range of a sustain loop, `end` is exclusive
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct LoopPoints {
+	pub start: usize,
+	pub end: usize,
+}
+
+impl LoopPoints {
+	pub fn new(start: usize, end: usize) -> Self { LoopPoints { start, end } }
+
+	pub fn len(&self) -> usize { self.end.saturating_sub(self.start) }
+
+	pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+	// Converts the points to another sample rate
+	pub fn rescale(&self, from: i32, to: i32) -> Self {
+		let scale = |v: usize| (v as u64 * to as u64 / from.max(1) as u64) as usize;
+		LoopPoints { start: scale(self.start), end: scale(self.end) }
+	}
+}
+
+// Reads the first loop of the `smpl` chunk of a RIFF WAVE file, FFmpeg doesn't expose it
+pub fn read_loop_points(path: &Path) -> Result<Option<LoopPoints>, Error> {
+	let map_err = |e: std::io::Error| Error::from(format!("Could not read {}: {}", path.display(), e));
+	let mut file = File::open(path).map_err(map_err)?;
+
+	let mut header = [0u8; 12];
+	if file.read_exact(&mut header).is_err() || &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+		return Ok(None);
+	}
+
+	let mut chunk = [0u8; 8];
+	while file.read_exact(&mut chunk).is_ok() {
+		let size = u32_le(&chunk[4..8]) as usize;
+		if &chunk[0..4] != b"smpl" {
+			// Chunks are padded to an even size
+			file.seek(SeekFrom::Current((size + size % 2) as i64)).map_err(map_err)?;
+			continue;
+		}
+
+		let mut data = vec![0u8; size];
+		file.read_exact(&mut data).map_err(map_err)?;
+		// 36 bytes of sampler info, then 24 byte loop records
+		if data.len() < 60 || u32_le(&data[28..32]) == 0 {
+			return Ok(None);
+		}
+		let (start, end) = (u32_le(&data[44..48]) as usize, u32_le(&data[48..52]) as usize);
+		// The smpl end is the last sample played
+		return Ok(Some(LoopPoints::new(start, end + 1)));
+	}
+
+	Ok(None)
+}
+
+fn u32_le(b: &[u8]) -> u32 {
+	u32::from(b[0]) | u32::from(b[1]) << 8 | u32::from(b[2]) << 16 | u32::from(b[3]) << 24
+}
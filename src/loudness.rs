@@ -0,0 +1,169 @@
+use std::path::Path;
+use crate::ffmpeg::*;
+use crate::error::Error;
+use crate::input::Input;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoudnessTarget {
+	pub name: &'static str,
+	// LUFS
+	pub integrated: f64,
+	pub tolerance: f64,
+	// dBTP
+	pub max_true_peak: f64,
+	// LU
+	pub max_lra: Option<f64>,
+}
+
+impl LoudnessTarget {
+	pub const EBU_R128: LoudnessTarget = LoudnessTarget {
+		name: "EBU R128", integrated: -23., tolerance: 0.5, max_true_peak: -1., max_lra: None
+	};
+	pub const ATSC_A85: LoudnessTarget = LoudnessTarget {
+		name: "ATSC A/85", integrated: -24., tolerance: 2., max_true_peak: -2., max_lra: None
+	};
+	pub const SPOTIFY: LoudnessTarget = LoudnessTarget {
+		name: "Spotify", integrated: -14., tolerance: 1., max_true_peak: -1., max_lra: None
+	};
+	pub const APPLE_MUSIC: LoudnessTarget = LoudnessTarget {
+		name: "Apple Music", integrated: -16., tolerance: 1., max_true_peak: -1., max_lra: None
+	};
+	pub const YOUTUBE: LoudnessTarget = LoudnessTarget {
+		name: "YouTube", integrated: -14., tolerance: 1., max_true_peak: -1., max_lra: None
+	};
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoudnessMeasurement {
+	pub integrated: f64,
+	pub lra: f64,
+	pub max_momentary: f64,
+	pub max_short_term: f64,
+	pub true_peak: f64,
+}
+
+impl LoudnessMeasurement {
+	pub fn check(&self, target: &LoudnessTarget) -> ComplianceReport {
+		let integrated_margin = target.tolerance - (self.integrated - target.integrated).abs();
+		let true_peak_margin = target.max_true_peak - self.true_peak;
+		let lra_margin = target.max_lra.map(|max| max - self.lra);
+
+		ComplianceReport {
+			target: target.clone(),
+			measurement: self.clone(),
+			integrated_margin,
+			true_peak_margin,
+			lra_margin,
+			passed: integrated_margin >= 0. && true_peak_margin >= 0. && lra_margin.map_or(true, |m| m >= 0.),
+		}
+	}
+}
+
+// Margins are positive when within the target
+#[derive(Clone, Debug, PartialEq)]
+pub struct ComplianceReport {
+	pub target: LoudnessTarget,
+	pub measurement: LoudnessMeasurement,
+	pub integrated_margin: f64,
+	pub true_peak_margin: f64,
+	pub lra_margin: Option<f64>,
+	pub passed: bool,
+}
+
+// Measures with the FFmpeg ebur128 filter
+pub fn measure_loudness(path: &Path) -> Result<LoudnessMeasurement, Error> {
+	let path = path.to_str().ok_or_else(|| Error::from(format!("Invalid path: {}", path.display())))?;
+	let input = Input::open(path, |i| pick_best_format(i, SampleFormat::F64(Type::Planar)))?;
+	let format = input.audio_format();
+	let mut graph = FilterGraph::new(&format, &format, "ebur128=metadata=1:peak=true")?;
+
+	let mut measurement = LoudnessMeasurement {
+		integrated: std::f64::NEG_INFINITY,
+		lra: 0.,
+		max_momentary: std::f64::NEG_INFINITY,
+		max_short_term: std::f64::NEG_INFINITY,
+		true_peak: std::f64::NEG_INFINITY,
+	};
+	let (mut packet, mut frame, mut filtered) = (
+		Packet::empty(),
+		Frame::empty().ok_or("Could not allocate a frame")?,
+		Frame::empty().ok_or("Could not allocate a frame")?
+	);
+
+	while match packet.read(input.format_ctx()) {
+		Err(Error::FFM(FFError::Eof)) => false,
+		Err(e) => return Err(e),
+		Ok(_) => true
+	} {
+		if packet.stream_id() == input.stream().index() {
+			match packet.send(input.codec_ctx()) {
+				Err(Error::FFM(FFError::Again)) => {},
+				Err(e) => return Err(e),
+				_ => {}
+			}
+			decode_frames(&input, &mut frame, &mut graph, &mut filtered, &mut measurement)?;
+		}
+		packet.reset();
+	}
+
+	match Packet::send_flush(input.codec_ctx()) {
+		Err(Error::FFM(FFError::Eof)) => {},
+		Err(e) => return Err(e),
+		_ => {}
+	}
+	decode_frames(&input, &mut frame, &mut graph, &mut filtered, &mut measurement)?;
+	graph.push_flush()?;
+	drain(&mut graph, &mut filtered, format.channels(), &mut measurement)?;
+
+	Ok(measurement)
+}
+
+pub fn check_compliance(path: &Path, target: &LoudnessTarget) -> Result<ComplianceReport, Error> {
+	Ok(measure_loudness(path)?.check(target))
+}
+
+fn decode_frames(
+	input: &Input, frame: &mut Frame, graph: &mut FilterGraph, filtered: &mut Frame,
+	measurement: &mut LoudnessMeasurement
+) -> Result<(), Error> {
+	while match frame.recieve(input.codec_ctx()) {
+		Err(Error::FFM(FFError::Again)) => false,
+		Err(Error::FFM(FFError::Eof)) => false,
+		Err(e) => return Err(e),
+		_ => true
+	} {
+		graph.push(frame)?;
+		drain(graph, filtered, input.audio_format().channels(), measurement)?;
+	}
+	Ok(())
+}
+
+fn drain(graph: &mut FilterGraph, frame: &mut Frame, channels: i32, measurement: &mut LoudnessMeasurement)
+	-> Result<(), Error>
+{
+	while match graph.pull(frame) {
+		Err(Error::FFM(FFError::Again)) => false,
+		Err(Error::FFM(FFError::Eof)) => false,
+		Err(e) => return Err(e),
+		_ => true
+	} {
+		let value = |key: &str| frame.metadata(&format!("lavfi.r128.{}", key)).and_then(|v| v.trim().parse::<f64>().ok());
+
+		// Integrated loudness and LRA are running values, the last frame holds the result
+		if let Some(integrated) = value("I") {
+			measurement.integrated = integrated;
+		}
+		if let Some(lra) = value("LRA") {
+			measurement.lra = lra;
+		}
+		measurement.max_momentary = measurement.max_momentary.max(value("M").unwrap_or(std::f64::NEG_INFINITY));
+		measurement.max_short_term = measurement.max_short_term.max(value("S").unwrap_or(std::f64::NEG_INFINITY));
+
+		for c in 0..channels {
+			if let Some(peak) = value(&format!("true_peaks_ch{}", c)) {
+				measurement.true_peak = measurement.true_peak.max(20. * peak.log10());
+			}
+		}
+	}
+	Ok(())
+}
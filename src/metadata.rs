@@ -0,0 +1,105 @@
+use std::slice;
+use crate::ffmpeg::*;
+
+// Tags such as ID3 frames or Vorbis comments, with the keys FFmpeg maps them to ("title", "artist", ...)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+	entries: Vec<(String, String)>,
+	// The tags of the stream, kept apart so they are written back to a stream rather than the container
+	stream: Vec<(String, String)>,
+	// The leading entries that are container tags
+	container_len: usize,
+}
+
+impl Metadata {
+	pub fn new(entries: Vec<(String, String)>) -> Self {
+		Metadata { container_len: entries.len(), entries, stream: Vec::new() }
+	}
+
+	// Container tags, followed by the tags of the stream missing from them. Ogg and Opus store their
+	// comments on the stream, most other formats in the container
+	pub fn from_input(format_ctx: &FormatContext, stream: &Stream) -> Self {
+		let mut metadata = Metadata::new(format_ctx.metadata_entries());
+		metadata.stream = stream.metadata_entries();
+		for (key, value) in &metadata.stream {
+			if metadata.get(key).is_none() {
+				metadata.entries.push((key.clone(), value.clone()));
+			}
+		}
+		metadata
+	}
+
+	// Keys match regardless of case, like FFmpeg's
+	pub fn get(&self, key: &str) -> Option<&str> {
+		self.entries.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v.as_str())
+	}
+
+	pub fn iter(&self) -> slice::Iter<(String, String)> { self.entries.iter() }
+
+	pub fn stream_entries(&self) -> slice::Iter<(String, String)> { self.stream.iter() }
+
+	pub fn container_entries(&self) -> slice::Iter<(String, String)> { self.entries[..self.container_len].iter() }
+
+	pub fn keys(&self) -> impl Iterator<Item=&str> { self.entries.iter().map(|(k, _)| k.as_str()) }
+
+	pub fn len(&self) -> usize { self.entries.len() }
+
+	pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+
+	pub fn title(&self) -> Option<&str> { self.get("title") }
+
+	pub fn artist(&self) -> Option<&str> { self.get("artist") }
+
+	pub fn album(&self) -> Option<&str> { self.get("album") }
+}
+
+impl<'a> IntoIterator for &'a Metadata {
+	type Item = &'a (String, String);
+	type IntoIter = slice::Iter<'a, (String, String)>;
+
+	fn into_iter(self) -> Self::IntoIter { self.entries.iter() }
+}
+
+// Tags written with the header of an output through `WriterOptions::metadata`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetadataBuilder {
+	pub(crate) container: Vec<(String, String)>,
+	pub(crate) stream: Vec<(String, String)>,
+}
+
+impl MetadataBuilder {
+	pub fn new() -> Self { Self::default() }
+
+	// Container tag, a later value for the same key wins
+	pub fn tag(mut self, key: &str, value: &str) -> Self {
+		set(&mut self.container, key, value);
+		self
+	}
+
+	// Tag of the audio stream, where Ogg based formats keep their comments
+	pub fn stream_tag(mut self, key: &str, value: &str) -> Self {
+		set(&mut self.stream, key, value);
+		self
+	}
+
+	pub fn title(self, title: &str) -> Self { self.tag("title", title) }
+
+	pub fn artist(self, artist: &str) -> Self { self.tag("artist", artist) }
+
+	pub fn album(self, album: &str) -> Self { self.tag("album", album) }
+
+	pub fn comment(self, comment: &str) -> Self { self.tag("comment", comment) }
+}
+
+// Copies the tags read from an input into the output container
+impl<'a> From<&'a Metadata> for MetadataBuilder {
+	fn from(metadata: &'a Metadata) -> Self {
+		let builder = metadata.container_entries().fold(MetadataBuilder::new(), |builder, (k, v)| builder.tag(k, v));
+		metadata.stream_entries().fold(builder, |builder, (k, v)| builder.stream_tag(k, v))
+	}
+}
+
+fn set(entries: &mut Vec<(String, String)>, key: &str, value: &str) {
+	entries.retain(|(k, _)| !k.eq_ignore_ascii_case(key));
+	entries.push((key.to_string(), value.to_string()));
+}
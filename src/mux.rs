@@ -0,0 +1,178 @@
+use crate::ffmpeg::*;
+use crate::error::Error;
+use crate::codec::Encoder;
+use crate::options::WriterOptions;
+use crate::info::Attachment;
+use crate::output::open_output_ctx;
+
+// Writes already encoded packets into a container, the header is written with the first packet
+pub struct Muxer {
+	format_ctx: FormatContext,
+	streams: Vec<(Stream, Option<BitstreamFilter>)>,
+	muxer_options: Vec<(String, String)>,
+	// Tags of every added stream
+	stream_metadata: Vec<(String, String)>,
+	// Added as streams with the header, so the indices of the other streams are kept
+	attachments: Vec<Attachment>,
+	header_written: bool,
+}
+
+impl Muxer {
+	pub fn open(path: &str) -> Result<Self, Error> {
+		Self::open_with(path, &WriterOptions::new())
+	}
+
+	// Uses the format, metadata, chapters, attachments, io, pacing and muxer options of `options`
+	pub fn open_with(path: &str, options: &WriterOptions) -> Result<Self, Error> {
+		let mut format_ctx = open_output_ctx(path, options)?;
+		for (key, value) in &options.metadata {
+			format_ctx.set_metadata(key, value)?;
+		}
+		for chapter in &options.chapters {
+			format_ctx.add_chapter(chapter.start, chapter.end, chapter.title.as_ref().map(|t| t.as_str()))?;
+		}
+		Ok(Muxer {
+			format_ctx, streams: Vec::new(), muxer_options: options.muxer_options.clone(),
+			stream_metadata: options.stream_metadata.clone(), attachments: options.attachments.clone(), header_written: false
+		})
+	}
+
+	pub fn format_ctx(&self) -> &FormatContext { &self.format_ctx }
+
+	pub(crate) fn format_ctx_mut(&mut self) -> &mut FormatContext { &mut self.format_ctx }
+
+	// Adds a stream for packets encoded with `params`, returns its index
+	pub fn add_stream(&mut self, params: &CodecParameters, time_base: Rational) -> Result<i32, Error> {
+		self.add_stream_with(params, time_base, None)
+	}
+
+	// Packets of the stream pass the bitstream filter `filter` before being written
+	pub fn add_filtered_stream(&mut self, params: &CodecParameters, time_base: Rational, filter: &str) -> Result<i32, Error> {
+		self.add_stream_with(params, time_base, Some(BitstreamFilter::new(filter, params, time_base)?))
+	}
+
+	// Adds a stream with the parameters, time base and tags of a demuxed stream
+	pub fn add_stream_from(&mut self, input: &Stream, filter: Option<&str>) -> Result<i32, Error> {
+		let index = match filter {
+			None => self.add_stream(&input.parameters(), input.time_base())?,
+			Some(filter) => self.add_filtered_stream(&input.parameters(), input.time_base(), filter)?
+		};
+		let (stream, _) = &mut self.streams[index as usize];
+		stream.copy_metadata_from(input)?;
+		// The tags of the options still win over the copied ones
+		for (key, value) in &self.stream_metadata {
+			stream.set_metadata(key, value)?;
+		}
+		Ok(index)
+	}
+
+	// Adds a stream for the packets of `encoder`, returns its index
+	pub fn add_encoder_stream(&mut self, encoder: &Encoder) -> Result<i32, Error> {
+		if self.header_written {
+			return Err(Error::from("Streams can't be added after the first packet"));
+		}
+		let mut stream = Stream::from_format(&self.format_ctx)?;
+		encoder.copy_parameters_to(&mut stream.parameters())?;
+		stream.set_time_base(encoder.time_base());
+		self.push_stream(stream, None)
+	}
+
+	// Tags a stream, e.g. with its "language" or "title"
+	pub fn set_stream_metadata(&mut self, index: i32, key: &str, value: &str) -> Result<(), Error> {
+		let (stream, _) = self.streams.get_mut(index as usize)
+			.ok_or_else(|| Error::from(format!("No stream {}", index)))?;
+		stream.set_metadata(key, value)
+	}
+
+	pub fn add_attachment(&mut self, attachment: Attachment) -> Result<(), Error> {
+		if self.header_written {
+			return Err(Error::from("Attachments can't be added after the first packet"));
+		}
+		self.attachments.push(attachment);
+		Ok(())
+	}
+
+	fn add_stream_with(
+		&mut self, params: &CodecParameters, time_base: Rational, filter: Option<BitstreamFilter>
+	) -> Result<i32, Error> {
+		if self.header_written {
+			return Err(Error::from("Streams can't be added after the first packet"));
+		}
+		let mut stream = Stream::from_format(&self.format_ctx)?;
+		match filter {
+			None => {
+				stream.parameters().copy_from(params, &self.format_ctx)?;
+				stream.set_time_base(time_base);
+			},
+			Some(ref filter) => {
+				stream.parameters().copy_from(&filter.output_parameters(), &self.format_ctx)?;
+				stream.set_time_base(filter.output_time_base());
+			}
+		}
+		self.push_stream(stream, filter)
+	}
+
+	fn push_stream(&mut self, mut stream: Stream, filter: Option<BitstreamFilter>) -> Result<i32, Error> {
+		for (key, value) in &self.stream_metadata {
+			stream.set_metadata(key, value)?;
+		}
+		let index = stream.index();
+		self.streams.push((stream, filter));
+		Ok(index)
+	}
+
+	// Writes `packet` to stream `index`, its timestamps are in `time_base`
+	pub fn write_packet(&mut self, packet: &mut Packet, index: i32, time_base: Rational) -> Result<(), Error> {
+		self.ensure_header()?;
+		let (stream, filter) = self.streams.get_mut(index as usize)
+			.ok_or_else(|| Error::from(format!("No stream {}", index)))?;
+		match filter {
+			None => write_to(&self.format_ctx, stream, packet, time_base),
+			Some(filter) => {
+				filter.send(packet)?;
+				drain_filter(&self.format_ctx, stream, filter)
+			}
+		}
+	}
+
+	pub fn finish(mut self) -> Result<(), Error> {
+		self.ensure_header()?;
+		for (stream, filter) in self.streams.iter_mut() {
+			if let Some(filter) = filter {
+				filter.send_flush()?;
+				drain_filter(&self.format_ctx, stream, filter)?;
+			}
+		}
+		self.format_ctx.write_trailer()
+	}
+
+	fn ensure_header(&mut self) -> Result<(), Error> {
+		if !self.header_written {
+			for attachment in &self.attachments {
+				self.format_ctx.add_attachment(&attachment.filename, &attachment.mime_type, &attachment.data)?;
+			}
+			self.format_ctx.write_header(&mut Dictionary::from_pairs(&self.muxer_options)?)?;
+			self.header_written = true;
+		}
+		Ok(())
+	}
+}
+
+fn drain_filter(format_ctx: &FormatContext, stream: &Stream, filter: &mut BitstreamFilter) -> Result<(), Error> {
+	let mut packet = Packet::empty();
+	while match filter.receive(&mut packet) {
+		Err(Error::FFM(FFError::Again)) | Err(Error::FFM(FFError::Eof)) => false,
+		Err(e) => return Err(e),
+		Ok(_) => true
+	} {
+		write_to(format_ctx, stream, &mut packet, filter.output_time_base())?;
+		packet.reset();
+	}
+	Ok(())
+}
+
+fn write_to(format_ctx: &FormatContext, stream: &Stream, packet: &mut Packet, time_base: Rational) -> Result<(), Error> {
+	packet.rescale_ts(time_base, stream.time_base());
+	packet.set_stream_index(stream.index());
+	packet.write_interleaved(format_ctx)
+}
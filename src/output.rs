@@ -1,12 +1,15 @@
 use std::{mem};
+use libc::c_void;
 use crate::sys::*;
 use crate::ffmpeg::*;
 use crate::error::Error;
+use crate::options::WriterOptions;
 
 pub struct Output {
 	format_ctx: FormatContext,
 	stream: Stream,
 	codec_ctx: EncoderOpen,
+	_log: Option<LogScope>,
 }
 
 impl Output {
@@ -14,21 +17,57 @@ impl Output {
 		-> Result<Self, Error>
 		where F: Fn(FormatIter) -> Option<SampleFormat>
 	{
-		let format_ctx = open_write(&path)?;
-		let mut codec_ctx = EncoderContext::create(&format_ctx)?;
+		Self::open_with(path, &WriterOptions::new(), format_picker, channel_layout, channel_layout.channels(), sample_rate)
+	}
+
+	// `channels` beyond those in `channel_layout` are written without speaker positions
+	pub fn open_with<F>(
+		path: &str, options: &WriterOptions, format_picker: F, channel_layout: ChannelLayout, channels: i32,
+		sample_rate: i32
+	) -> Result<Self, Error>
+		where F: Fn(FormatIter) -> Option<SampleFormat>
+	{
+		let mut format_ctx = open_output_ctx(path, options)?;
+		for (key, value) in &options.metadata {
+			format_ctx.set_metadata(key, value)?;
+		}
+		let mut codec_ctx = EncoderContext::create(&format_ctx, options.codec.as_ref().map(|c| c.as_str()))?;
+
+		let mut log = options.log.as_ref().map(LogScope::new);
+		if let Some(ref mut log) = log {
+			log.attach(format_ctx.as_ptr() as *const c_void);
+			log.attach(codec_ctx.as_ptr() as *const c_void);
+		}
 
 		let format_iter = codec_ctx.ctx().codec().formats()
 			.ok_or("Could not find appropriate sample format")?;
 		let sample_format = format_picker(format_iter)
 			.ok_or("Could not find appropriate sample format")?;
+		let sample_rate = negotiate_sample_rate(codec_ctx.ctx().codec(), sample_rate, options.strict_sample_rate)?;
 
-		codec_ctx.ctx_mut().set_channel_layout(channel_layout);
+		match channel_layout.channels() == channels {
+			true => codec_ctx.ctx_mut().set_channel_layout(channel_layout),
+			false => codec_ctx.ctx_mut().set_unspecified_layout(channels),
+		}
 		codec_ctx.ctx_mut().set_sample_format(sample_format);
 		codec_ctx.ctx_mut().set_sample_rate(sample_rate);
+		codec_ctx.ctx_mut().set_time_base(Rational::samples(sample_rate));
+		if let Some(compression_level) = options.compression_level {
+			codec_ctx.ctx_mut().set_compression_level(compression_level);
+		}
+		if let Some(quality) = options.quality {
+			codec_ctx.ctx_mut().set_quality(quality);
+		}
 
 		let mut stream = Stream::from_format(&format_ctx)?;
-		stream.as_mut_ref().time_base.den = sample_rate;
-		stream.as_mut_ref().time_base.num = 1;
+		stream.set_time_base(Rational::samples(sample_rate));
+		if let Some(ref language) = options.language {
+			stream.set_metadata("language", language)?;
+		}
+		for (key, value) in &options.stream_metadata {
+			stream.set_metadata(key, value)?;
+		}
+		add_chapters_and_attachments(&mut format_ctx, options)?;
 
 		if (format_ctx.get_flags() & AVFMT_GLOBALHEADER) != 0 {
 			codec_ctx.as_mut_ref().flags |= unsafe { mem::transmute::<u32, i32>(AV_CODEC_FLAG_GLOBAL_HEADER) };
@@ -36,7 +75,7 @@ impl Output {
 
 		let codec_ctx = EncoderOpen::open(codec_ctx, &mut stream)?;
 
-		Ok(Output { format_ctx, stream, codec_ctx })
+		Ok(Output { format_ctx, stream, codec_ctx, _log: log })
 	}
 
 	pub fn format_ctx(&self) -> &FormatContext { &self.format_ctx }
@@ -51,6 +90,8 @@ impl Output {
 
 	pub fn sample_rate(&self) -> i32 { self.codec_ctx.ctx().ctx().sample_rate() }
 
+	pub fn audio_format(&self) -> AudioFormat { self.codec_ctx.ctx().ctx().audio_format() }
+
 	pub fn frame_size(&self) -> usize {
 		(match self.codec_ctx.ctx().ctx().frame_size() {
 			0 => AV_CODEC_CAP_VARIABLE_FRAME_SIZE as i32,
@@ -60,21 +101,71 @@ impl Output {
 
 	pub fn converter(&self, src_fmt: AudioFormat)
 		-> Result<Converter, Error> {
-		Converter::new(
+		self.converter_with_map(src_fmt, None)
+	}
+
+	pub fn converter_with_map(&self, src_fmt: AudioFormat, channel_map: Option<&[i32]>)
+		-> Result<Converter, Error> {
+		Converter::with_channel_map(
 			src_fmt,
-			AudioFormat::new(self.channel_layout(), self.sample_format(), self.sample_rate())
+			self.audio_format(),
+			channel_map
 		)
 	}
 
+	pub fn write_packet(&mut self, packet: &mut Packet) -> Result<(), Error> {
+		packet.rescale_ts(self.codec_ctx.ctx().ctx().time_base(), self.stream.time_base());
+		packet.set_stream_index(self.stream.index());
+		packet.write(&self.format_ctx)?;
+		packet.reset();
+		Ok(())
+	}
+
 	pub fn new_frame(&self) -> Result<Frame, Error> {
-		let mut frame = Frame::empty().unwrap();
-		frame.set_channel_layout(self.channel_layout());
-		frame.set_sample_format(self.sample_format());
-		frame.set_sample_rate(self.sample_rate());
-		frame.set_nb_samples(self.frame_size() as i32);
+		Frame::alloc(self.audio_format(), self.frame_size() as i32)
+	}
+}
 
-		unsafe { ffm_op!(av_frame_get_buffer(frame.as_mut_ptr(), 0))? };
+// Writes through a `Throttle` when the options pace or observe the writes
+pub(crate) fn open_output_ctx(path: &str, options: &WriterOptions) -> Result<FormatContext, Error> {
+	let mut io_options = Dictionary::from_pairs(&options.io_options)?;
+	let format = options.format.as_ref().map(|f| f.as_str());
+	let pacing = match options.pacing {
+		None => return open_write_with(path, format, &mut io_options),
+		Some(ref pacing) => pacing.clone()
+	};
+
+	let sink = UrlWriter::open(path, &mut io_options)?;
+	let io = match sink.is_seekable() {
+		true => IoContext::seekable_writer(Throttle::new(sink, pacing))?,
+		false => IoContext::writer(Throttle::new(sink, pacing))?
+	};
+	open_write_io(path, format, io)
+}
+
+pub(crate) fn negotiate_sample_rate(codec: &Codec, sample_rate: i32, strict: bool) -> Result<i32, Error> {
+	let supported = match codec.sample_rates() {
+		Some(supported) if !supported.contains(&sample_rate) => supported,
+		_ => return Ok(sample_rate)
+	};
+	match (pick_sample_rate(&supported, sample_rate), strict) {
+		(Some(nearest), false) => Ok(nearest),
+		_ => {
+			let supported: Vec<String> = supported.iter().map(|r| r.to_string()).collect();
+			Err(Error::Unsupported(format!(
+				"The encoder doesn't support {} Hz, only {} Hz", sample_rate, supported.join(", ")
+			)))
+		}
+	}
+}
 
-		Ok(frame)
+// Attachments are streams, so they are added after the audio stream
+fn add_chapters_and_attachments(format_ctx: &mut FormatContext, options: &WriterOptions) -> Result<(), Error> {
+	for chapter in &options.chapters {
+		format_ctx.add_chapter(chapter.start, chapter.end, chapter.title.as_ref().map(|t| t.as_str()))?;
+	}
+	for attachment in &options.attachments {
+		format_ctx.add_attachment(&attachment.filename, &attachment.mime_type, &attachment.data)?;
 	}
+	Ok(())
 }
@@ -1,60 +1,46 @@
 use std::ptr;
+use std::fs::File;
+use std::io::{Cursor, Read, Seek};
+use std::time::Duration;
 use litcontainers::*;
 use litaudio::*;
 use crate::sys::*;
 use crate::ffmpeg::*;
 use crate::error::Error;
-
-pub struct Input {
-	format_ctx: FormatContext,
-	stream: Stream,
-	codec_ctx: DecoderOpen,
+use crate::input::Input;
+use crate::info::{AudioInfo, CoverArt};
+use crate::options::{ReaderOptions, Crop, ChannelOrder};
+use crate::samples::*;
+use crate::timecode::Timecode;
+use crate::metadata::Metadata;
+use crate::time::*;
+
+// Skips of at least this many seconds seek instead of decoding up to the target
+const SKIP_SEEK_DURATION: usize = 10;
+// Seconds reserved up front by `read` when the input has no duration, the buffer grows from there
+const UNKNOWN_DURATION: usize = 30;
+// Frame size assumed for codecs without a fixed one unless `ReaderOptions::max_frame_size` says otherwise
+const DEFAULT_MAX_FRAME_SIZE: usize = 4096;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SilenceEvent {
+	// Seconds into the stream at which the silence began
+	Start(f64),
+	// The level recovered `at` seconds into the stream after `duration` seconds of silence
+	End { at: f64, duration: f64 },
 }
 
-impl Input {
-	pub fn open<F>(path: &str, format_picker: F) -> Result<Self, Error>
-		where F: Fn(FormatIter) -> Option<SampleFormat>
-	{
-		let format_ctx = open_read(path)?;
-		let stream = format_ctx.get_audio_stream()
-			.ok_or("Could not find any audio stream in the file")?;
-		let codec_params = stream.parameters();
-		let mut codec_ctx = DecoderContext::create(&codec_params)?;
-
-		let format_iter = codec_ctx.ctx().codec().formats()
-			.ok_or("Could not find appropriate sample format")?;
-		let sample_format = format_picker(format_iter)
-			.ok_or("Could not find appropriate sample format")?;
-
-		codec_ctx.set_request_sample_fmt(sample_format);
-		let codec_ctx = DecoderOpen::open(codec_ctx)?;
-
-		Ok(Input { format_ctx, stream, codec_ctx })
-	}
-
-	pub fn format_ctx(&self) -> &FormatContext { &self.format_ctx }
-
-	pub fn stream(&self) -> &Stream { &self.stream }
-
-	pub fn codec_ctx(&self) -> &DecoderOpen { &self.codec_ctx }
-
-	pub fn estimated_sample_count(&self) -> usize {
-		(self.format_ctx.get_duration() * self.codec_ctx.ctx().ctx().sample_rate() as usize) / AV_TIME_BASE as usize
-	}
-
-	pub fn channel_layout(&self) -> ChannelLayout { self.codec_ctx.ctx().ctx().channel_layout() }
-
-	pub fn sample_format(&self) -> SampleFormat { self.codec_ctx.ctx().sample_fmt() }
-
-	pub fn sample_rate(&self) -> i32 { self.codec_ctx.ctx().ctx().sample_rate() }
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Discontinuity {
+	// No samples were decoded for `duration` seconds from `at` seconds into the stream
+	Gap { at: f64, duration: f64 },
+	// The frame starting at `at` seconds repeats `duration` seconds of the previous one
+	Overlap { at: f64, duration: f64 },
+}
 
-	pub fn converter(&self, dst_fmt: AudioFormat)
-		-> Result<Converter, Error> {
-		Converter::new(
-			AudioFormat::new(self.channel_layout(), self.sample_format(), self.sample_rate()),
-			dst_fmt
-		)
-	}
+struct ReaderFilter {
+	graph: FilterGraph,
+	frame: Frame,
 }
 
 pub struct Reader<'a, T, P, S>
@@ -64,6 +50,24 @@ pub struct Reader<'a, T, P, S>
 	output: AudioContainer<T, P, S>,
 	cursor: SliceMut<'a, T, S::Rows, S::RowStride, Dynamic, S::ColStride>,
 	converter: Option<Converter>,
+	filter: Option<ReaderFilter>,
+	src_format: AudioFormat,
+	dst_format: AudioFormat,
+	stream_title: Option<String>,
+	on_stream_title: Option<Box<dyn FnMut(&str)>>,
+	on_silence: Option<Box<dyn FnMut(SilenceEvent)>>,
+	on_discontinuity: Option<Box<dyn FnMut(Discontinuity)>>,
+	// Expected timestamp of the next frame in input samples, unknown after a seek
+	next_pts: Option<i64>,
+	frame: Option<Frame>,
+	options: ReaderOptions,
+	estimated_sample_count: usize,
+	// Index of the next decoded sample, unknown after a seek until a frame arrives
+	position: Option<usize>,
+	skip_to: usize,
+	// Silence inserted by a positive offset, the output timeline runs this far ahead of the input
+	lead_in: usize,
+	eof: bool,
 	sample_count: usize
 }
 
@@ -71,28 +75,107 @@ impl<'a, T, P, S> Reader<'a, T, P, S>
 	where T: Sample, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
 {
 	pub fn open(path: &str, channel_count: Option<usize>) -> Result<Self, Error> {
-		let input = Input::open(
-			&path,
+		Self::open_with(path, ReaderOptions::new().channel_count(channel_count))
+	}
+
+	pub fn open_with(path: &str, options: ReaderOptions) -> Result<Self, Error> {
+		let _span = timed_span!(DEBUG, "open", path);
+		let input_format = options.input_format.as_ref().map(|f| f.as_str());
+		let mut format_options = Dictionary::from_pairs(&options.format_options)?;
+		let mut log = options.log.as_ref().map(LogScope::new);
+		let format_ctx = open_read_scoped(&path, input_format, &mut format_options, log.as_mut())?;
+
+		// The opened input is kept unless its mono streams are merged, amovie then opens the file itself
+		let format_ctx = match options.merge_mono_streams {
+			false => format_ctx,
+			true => match merge_mono_graph(path, input_format, &format_ctx) {
+				None => format_ctx,
+				Some(graph) => {
+					drop(format_ctx);
+					open_read_scoped(&graph, Some("lavfi"), &mut Dictionary::new(), log.as_mut())?
+				}
+			}
+		};
+		Self::from_format_ctx(format_ctx, options)
+	}
+
+	// Decodes an already open file, so its open flags and sharing are up to the caller. A raw descriptor
+	// or handle is passed in through `File::from_raw_fd` or `File::from_raw_handle`
+	pub fn from_file(file: File, options: ReaderOptions) -> Result<Self, Error> {
+		Self::from_reader(file, options)
+	}
+
+	// Decodes from any seekable source, such as audio received over the network or embedded in another file
+	pub fn from_reader<R: Read + Seek + Send + 'static>(reader: R, options: ReaderOptions) -> Result<Self, Error> {
+		Self::open_io(IoContext::seekable_reader(reader)?, options)
+	}
+
+	// Decodes an encoded file held in memory, the data is copied
+	pub fn from_slice(data: &[u8], options: ReaderOptions) -> Result<Self, Error> {
+		Self::from_reader(Cursor::new(data.to_vec()), options)
+	}
+
+	// Decodes what `io` reads, `ReaderOptions::input_format` spares probing streams without a file name
+	pub fn open_io(io: IoContext, options: ReaderOptions) -> Result<Self, Error> {
+		let mut format_options = Dictionary::from_pairs(&options.format_options)?;
+		let mut log = options.log.as_ref().map(LogScope::new);
+		let input_format = options.input_format.clone();
+		let format_ctx = open_read_io(io, input_format.as_ref().map(|f| f.as_str()), &mut format_options, log.as_mut())?;
+		Self::from_format_ctx(format_ctx, options)
+	}
+
+	fn from_format_ctx(format_ctx: FormatContext, mut options: ReaderOptions) -> Result<Self, Error> {
+		options.channel_count = S::Rows::try_to_usize().or(options.channel_count);
+		let input = Input::from_format_ctx_with(
+			format_ctx,
+			&options,
 			|i| pick_best_format(i, SampleFormat::from_type::<T, P>())
 		)?;
 
-		let channel_count = match (channel_count, S::Rows::try_to_usize()) {
-			(None, None) => S::Rows::from_usize(input.channel_layout().channels() as usize),
+		let src_format = input.audio_format();
+		let channel_count = match (options.channel_count, S::Rows::try_to_usize()) {
+			(None, None) => S::Rows::from_usize(src_format.channels() as usize),
 			(Some(c), None) => S::Rows::from_usize(c),
 			(_, Some(c)) => S::Rows::from_usize(c),
 		};
 
+		let sample_rate = options.target_sample_rate.unwrap_or(input.sample_rate());
+		let estimated_sample_count = input.estimated_sample_count()
+			.map(|n| (n as u128 * sample_rate as u128 / input.sample_rate().max(1) as u128) as usize)
+			.unwrap_or(UNKNOWN_DURATION * sample_rate as usize);
+
+		// The full estimate is only reserved when reading everything, streaming keeps a small buffer
 		let mut output = AudioContainer::zeros(
-			Size::new(channel_count, D!(input.estimated_sample_count()))
+			Size::new(channel_count, D!(0))
 		);
-		output.set_sample_rate(input.sample_rate());
+		output.set_sample_rate(sample_rate);
+
+		// Channels without a layout stay so, instead of being remixed from a guessed one
+		let dst_format = match src_format.is_unspecified() && channel_count.value() == src_format.channels() as usize {
+			true => AudioFormat::unspecified(src_format.channels(), SampleFormat::from_type::<T, P>(), sample_rate),
+			false => AudioFormat::from_storage(&output)
+		};
 
 		let use_converter = input.sample_format() != SampleFormat::from_type::<T, P>()
-			|| channel_count.value() != input.channel_layout().channels() as usize;
+			|| channel_count.value() != src_format.channels() as usize
+			|| sample_rate != input.sample_rate()
+			|| channel_map(&src_format, &dst_format, options.channel_order).is_some()
+			|| options.mix_matrix.is_some();
 
+		if use_converter && !options.allow_converter {
+			return Err(conversion_error(&src_format, &dst_format));
+		}
 		let converter = match use_converter {
 			false => None,
-			true => Some(input.converter(AudioFormat::from_storage(&output))?)
+			true => Some(create_converter(src_format, dst_format, &options)?)
+		};
+
+		let filter = match options.filter_description(src_format.sample_rate) {
+			None => None,
+			Some(description) => Some(ReaderFilter {
+				graph: FilterGraph::new(&src_format, &src_format, &description)?,
+				frame: Frame::empty().unwrap(),
+			})
 		};
 
 		let cursor = SliceBase::new(
@@ -104,28 +187,303 @@ impl<'a, T, P, S> Reader<'a, T, P, S>
 				)},
 		).into();
 
-		Ok(Reader { input, output, cursor, converter, sample_count: 0 })
+		let offset = seconds_to_samples(options.offset, sample_rate);
+		let mut reader = Reader {
+			input, output, cursor, converter, filter, src_format, dst_format,
+			stream_title: None, on_stream_title: None, on_silence: None, on_discontinuity: None, next_pts: None, frame: Frame::empty(), options,
+			estimated_sample_count, position: Some(0), skip_to: 0, lead_in: offset.max(0) as usize, eof: false, sample_count: 0
+		};
+
+		// Dropped samples are trimmed like after a seek, inserted ones are buffered up front
+		if offset < 0 {
+			reader.skip_to = -offset as usize;
+		} else if offset > 0 {
+			reader.buffer_silence(offset as usize);
+		}
+		let frame_size = reader.max_frame_size();
+		reader.reserve(frame_size);
+		Ok(reader)
+	}
+
+	// Generates audio with a lavfi source such as "sine=frequency=440:duration=5", "anoisesrc=d=5:c=pink" or
+	// "aevalsrc=0:d=5" for silence. Sources without a duration like "anullsrc" never end, so only stream them
+	pub fn open_lavfi(graph: &str) -> Result<Self, Error> {
+		Self::open_lavfi_with(graph, ReaderOptions::new())
+	}
+
+	pub fn open_lavfi_with(graph: &str, options: ReaderOptions) -> Result<Self, Error> {
+		Self::open_with(graph, options.input_format("lavfi"))
+	}
+
+	pub fn info(&self) -> AudioInfo { self.input.info() }
+
+	pub fn cover_art(&self) -> Vec<CoverArt> { self.input.cover_art() }
+
+	pub fn metadata(&self) -> Metadata { self.input.metadata() }
+
+	pub fn timecode(&self) -> Option<Timecode> { self.input.timecode() }
+
+	pub fn time_reference(&self) -> Option<u64> { self.input.time_reference() }
+
+	pub fn channels(&self) -> usize { self.output.channels() }
+
+	pub fn sample_rate(&self) -> i32 { self.output.sample_rate() }
+
+	pub fn seek(&mut self, position: Duration) -> Result<(), Error> {
+		self.seek_sample(duration_to_samples(position, self.dst_format.sample_rate))
+	}
+
+	// Buffered samples are dropped and the converter and filters restarted. Demuxers land on an earlier
+	// packet, the samples decoded before `sample` are trimmed so decoding continues exactly from it
+	pub fn seek_sample(&mut self, sample: usize) -> Result<(), Error> {
+		let input_sample = sample.saturating_sub(self.lead_in);
+		self.input.seek(samples_to_duration(input_sample, self.dst_format.sample_rate))?;
+		self.position = None;
+		self.next_pts = None;
+		self.skip_to = input_sample;
+		self.sample_count = 0;
+		self.eof = false;
+		// Seeking into the inserted silence buffers the rest of it again
+		self.buffer_silence(self.lead_in.saturating_sub(sample));
+
+		// The resampler still holds input from before the seek
+		if self.converter.is_some() {
+			self.converter = Some(create_converter(self.src_format, self.dst_format, &self.options)?);
+		}
+
+		self.filter = match self.options.filter_description(self.src_format.sample_rate) {
+			None => None,
+			Some(description) => Some(ReaderFilter {
+				graph: FilterGraph::new(&self.src_format, &self.src_format, &description)?,
+				frame: Frame::empty().ok_or("Could not allocate a frame")?,
+			})
+		};
+		Ok(())
+	}
+
+	// Advances `count` samples past the current position. Far targets are sought, near ones (or all
+	// on unseekable inputs) decoded and dropped without converting whole frames before the target
+	pub fn skip_samples(&mut self, count: usize) -> Result<(), Error> {
+		let buffered = count.min(self.sample_count);
+		self.discard_samples(buffered);
+		if buffered == count {
+			return Ok(());
+		}
+
+		let target = self.position() + count - buffered;
+		let sample_rate = self.dst_format.sample_rate as usize;
+		if count - buffered >= SKIP_SEEK_DURATION * sample_rate && self.seek_sample(target).is_ok() {
+			return Ok(());
+		}
+
+		self.skip_to = target.saturating_sub(self.lead_in);
+		while self.sample_count == 0 && self.decode_next()? {}
+		Ok(())
+	}
+
+	pub fn on_stream_title<F>(&mut self, callback: F)
+		where F: FnMut(&str) + 'static
+	{
+		self.on_stream_title = Some(Box::new(callback));
+	}
+
+	// Requires `ReaderOptions::detect_silence`, fires as the decoding reaches the silence and the recovery
+	pub fn on_silence<F>(&mut self, callback: F)
+		where F: FnMut(SilenceEvent) + 'static
+	{
+		self.on_silence = Some(Box::new(callback));
+	}
+
+	// Requires `ReaderOptions::detect_gaps`, fires as the decoding reaches the discontinuity
+	pub fn on_discontinuity<F>(&mut self, callback: F)
+		where F: FnMut(Discontinuity) + 'static
+	{
+		self.on_discontinuity = Some(Box::new(callback));
 	}
 
 	pub fn read(mut self) -> Result<AudioContainer<T, P, S>, Error> {
-		let mut frame = Frame::empty().unwrap();
-		let mut packet = Packet::empty();
+		if self.output.samples() < self.estimated_sample_count {
+			self.output.set_samples(self.estimated_sample_count);
+		}
 
-		while match self.read_frame(&mut packet, &mut frame) {
-			Err(Error::FFM(FFError::Again)) => true,
-			Err(Error::FFM(FFError::Eof)) => false,
-			Err(e) => return Err(e),
-			Ok(_) => true
+		let fixed_length = self.options.fixed_length;
+		while match fixed_length {
+			Some((length, Crop::Start)) if self.sample_count >= length => false,
+			_ => self.decode_next()?
 		} {}
 
+		if let Some((length, crop)) = fixed_length {
+			if crop == Crop::Center && self.sample_count > length {
+				self.discard_samples((self.sample_count - length) / 2);
+			}
+			if self.sample_count < length {
+				self.output.set_samples(length);
+				fill_samples(&mut self.output, self.sample_count, length - self.sample_count, from_normalized::<T>(0.));
+			}
+			self.sample_count = length;
+		}
+
+		if let Some(target_dbfs) = self.options.peak_normalize {
+			let peak = peak_level(&self.output, 0, self.sample_count);
+			if peak > 0. {
+				apply_gain(&mut self.output, 0, self.sample_count, 10f64.powf(target_dbfs / 20.) / peak);
+			}
+		}
+
 		self.output.set_samples(self.sample_count);
 		Ok(self.output)
 	}
 
+	// Decodes only the samples in [start, end), seeking to `start` and trimming the pre-roll before it.
+	// Inputs that can't seek are decoded up to `start`. Ends early when the input is shorter
+	pub fn read_range(mut self, start: Duration, end: Duration) -> Result<AudioContainer<T, P, S>, Error> {
+		if end < start {
+			return Err(Error::from(format!("The range end {:?} precedes its start {:?}", end, start)));
+		}
+		let sample_rate = self.dst_format.sample_rate;
+		let (first, last) = (duration_to_samples(start, sample_rate), duration_to_samples(end, sample_rate));
+		if self.seek_sample(first).is_err() {
+			let position = self.position();
+			self.skip_samples(first.saturating_sub(position))?;
+		}
+
+		let count = last - first;
+		while self.sample_count < count && self.decode_next()? {}
+		let count = count.min(self.sample_count);
+		Ok(self.take_samples(count, count))
+	}
+
+	// Yields windows of `size` samples starting every `hop` samples, the last one being zero padded
+	pub fn windows(self, size: usize, hop: usize) -> Windows<'a, T, P, S> {
+		Windows { reader: self, size, hop: hop.max(1), emitted: false }
+	}
+
+	// Yields consecutive blocks of `size` samples as they are decoded, the last one holds the remainder
+	pub fn chunks(self, size: usize) -> Chunks<'a, T, P, S> {
+		Chunks { reader: self, size: size.max(1) }
+	}
+
+	// Invokes `callback` with the sample position of each block, only one block is kept in memory
+	pub fn for_each_block<F>(mut self, size: usize, mut callback: F) -> Result<usize, Error>
+		where F: FnMut(usize, &AudioContainer<T, P, S>)
+	{
+		let mut block = AudioContainer::zeros(Size::new(self.output.channel_dim(), D!(size)));
+		block.set_sample_rate(self.output.sample_rate());
+
+		let mut position = 0;
+		loop {
+			while self.sample_count < size && self.decode_next()? {}
+			if self.sample_count == 0 {
+				break;
+			}
+
+			let count = size.min(self.sample_count);
+			copy_samples(&self.output, 0, &mut block, 0, count);
+			block.set_samples(count);
+			callback(position, &block);
+
+			self.discard_samples(count);
+			position += count;
+		}
+
+		Ok(position)
+	}
+
+	pub fn buffered_samples(&self) -> usize { self.sample_count }
+
+	// Samples decoded but still held by the resampler in the output sample rate, `position` lags the
+	// decoded timestamps by this much
+	pub fn resampler_delay(&self) -> usize {
+		self.converter.as_ref().map_or(0, |c| c.delay().max(0) as usize)
+	}
+
+	// Most samples a decoded frame adds to the buffer in the output sample rate, including those held back
+	// by the resampler. Reading blocks of up to this size keeps the buffer from growing
+	pub fn max_frame_size(&self) -> usize {
+		let frame_size = self.options.max_frame_size.or(self.input.frame_size()).unwrap_or(DEFAULT_MAX_FRAME_SIZE);
+		match self.converter {
+			None => frame_size,
+			Some(ref converter) => converter.get_sample_count(frame_size as i32).max(0) as usize
+		}
+	}
+
+	// Index of the first buffered sample in the output sample rate, counting silence inserted by the offset
+	pub fn position(&self) -> usize {
+		(self.position.unwrap_or(self.skip_to) + self.lead_in).saturating_sub(self.sample_count)
+	}
+
+	pub fn is_eof(&self) -> bool { self.eof }
+
+	// Decodes the next packet into the buffer, returns false once the input is exhausted
+	pub fn decode_next(&mut self) -> Result<bool, Error> {
+		if self.eof {
+			return Ok(false);
+		}
+
+		let mut frame = self.frame.take().ok_or("Could not allocate a frame")?;
+		let res = self.read_frame(&mut Packet::empty(), &mut frame);
+		let res = match res {
+			Err(Error::FFM(FFError::Again)) => Ok(true),
+			Err(Error::FFM(FFError::Eof)) => self.finish(&mut frame).map(|_| false),
+			Err(e) => Err(e),
+			Ok(_) => Ok(true)
+		};
+		self.frame = Some(frame);
+		res
+	}
+
+	fn finish(&mut self, frame: &mut Frame) -> Result<(), Error> {
+		self.eof = true;
+
+		// Drain the frames still held by the decoder, these carry the end trimming of e.g. Opus
+		match Packet::send_flush(&self.input.codec_ctx()) {
+			Err(Error::FFM(FFError::Eof)) => {},
+			Err(e) => return Err(e),
+			_ => {}
+		}
+		self.receive_frames(frame)?;
+
+		if let Some(mut filter) = self.filter.take() {
+			filter.graph.push_flush()?;
+			self.drain_filter(&mut filter)?;
+		}
+		self.flush_converter()
+	}
+
+	// Moves up to `out.len() / channels` buffered samples into `out` interleaved, returns the sample count
+	pub fn take_interleaved(&mut self, out: &mut [T]) -> usize {
+		let channels = self.output.channels();
+		let count = (out.len() / channels.max(1)).min(self.sample_count);
+		for c in 0..channels {
+			for (i, v) in channel_iter(&self.output, c).take(count).enumerate() {
+				out[i * channels + c] = v;
+			}
+		}
+		self.discard_samples(count);
+		count
+	}
+
+	pub fn take_samples(&mut self, size: usize, consume: usize) -> AudioContainer<T, P, S> {
+		let mut block = AudioContainer::zeros(Size::new(self.output.channel_dim(), D!(size)));
+		block.set_sample_rate(self.output.sample_rate());
+		copy_samples(&self.output, 0, &mut block, 0, size.min(self.sample_count));
+		self.discard_samples(consume);
+		block
+	}
+
+	pub fn discard_samples(&mut self, count: usize) {
+		let count = count.min(self.sample_count);
+		shift_left(&mut self.output, count, self.sample_count - count);
+		self.sample_count -= count;
+	}
+
 	fn read_frame(&mut self, packet: &mut Packet, frame: &mut Frame) -> Result<(), Error> {
 		packet.read(&self.input.format_ctx())?;
+		let _span = timed_span!(TRACE, "decode", stream = packet.stream_id(), pts = packet.pts(), size = packet.size() as u64);
+		self.update_stream_title();
 
-		if packet.stream_id() != self.input.stream().id() {
+		// FLV (RTMP) assigns stream ids by media type, so match on the index
+		if packet.stream_id() != self.input.stream().index() {
 			packet.reset();
 			return Ok(());
 		}
@@ -135,29 +493,232 @@ impl<'a, T, P, S> Reader<'a, T, P, S>
 			Err(e) => return Err(e),
 			_ => {}
 		}
+		packet.reset();
 
+		self.receive_frames(frame)
+	}
+
+	fn receive_frames(&mut self, frame: &mut Frame) -> Result<(), Error> {
 		while match frame.recieve(&self.input.codec_ctx()) {
 			Err(Error::FFM(FFError::Again)) => false,
+			Err(Error::FFM(FFError::Eof)) => false,
 			Err(e) => return Err(e),
 			_ => true
 		} {
-			if self.output.samples() < self.sample_count + frame.nb_samples() as usize {
-				self.output.set_samples(self.sample_count + frame.nb_samples() as usize);
+			self.update_format(frame)?;
+			self.check_continuity(frame);
+			if self.position.is_none() {
+				self.position = Some(self.frame_position(frame));
+			}
+
+			// Without a resampler or filter holding state, frames before a skip target are dropped as is
+			if let (None, None, Some(position)) = (&self.converter, &self.filter, self.position) {
+				let end = position + frame.nb_samples() as usize;
+				if end <= self.skip_to {
+					self.position = Some(end);
+					continue;
+				}
+			}
+
+			match self.filter.take() {
+				None => self.append_frame(frame)?,
+				Some(mut filter) => {
+					filter.graph.push(frame)?;
+					let res = self.drain_filter(&mut filter);
+					self.filter = Some(filter);
+					res?;
+				}
 			}
+		}
 
-			let buffer_size = self.output.samples() - self.sample_count;
-			self.cursor.storage_mut().storage_mut().shift_col_to(&mut self.output, self.sample_count, buffer_size);
+		Ok(())
+	}
 
-			self.copy_frame_to_cursor(frame)?;
+	fn update_stream_title(&mut self) {
+		if let Some(ref mut callback) = self.on_stream_title {
+			let stream_title = self.input.stream_title();
+			if stream_title.is_some() && stream_title != self.stream_title {
+				callback(stream_title.as_ref().unwrap());
+				self.stream_title = stream_title;
+			}
+		}
+	}
 
-			self.sample_count += frame.nb_samples() as usize;
+	fn report_silence(&mut self, frame: &Frame) {
+		if let Some(ref mut callback) = self.on_silence {
+			let value = |key: &str| frame.metadata(key).and_then(|v| v.trim().parse::<f64>().ok());
+			if let Some(start) = value("lavfi.silence_start") {
+				callback(SilenceEvent::Start(start));
+			}
+			if let (Some(at), Some(duration)) = (value("lavfi.silence_end"), value("lavfi.silence_duration")) {
+				callback(SilenceEvent::End { at, duration });
+			}
 		}
+	}
+
+	fn check_continuity(&mut self, frame: &Frame) {
+		let (tolerance, fill) = match self.options.gap_detection {
+			None => return,
+			Some(detection) => detection
+		};
+		let sample_rate = self.src_format.sample_rate;
+		// AV_NOPTS_VALUE
+		let pts = match frame.pts() {
+			pts if pts == i64::min_value() => self.next_pts,
+			pts => Some(pts_to_samples(pts, self.input.stream().time_base(), sample_rate))
+		};
 
+		if let (Some(expected), Some(actual)) = (self.next_pts, pts) {
+			let difference = actual - expected;
+			if difference.abs() as usize > duration_to_samples(tolerance, sample_rate) {
+				let at = samples_to_seconds(expected, sample_rate);
+				let duration = samples_to_seconds(difference.abs(), sample_rate);
+				if let Some(ref mut callback) = self.on_discontinuity {
+					callback(match difference > 0 {
+						true => Discontinuity::Gap { at, duration },
+						false => Discontinuity::Overlap { at, duration }
+					});
+				}
+				if fill && difference > 0 && self.position.is_some() {
+					self.insert_silence((difference * self.dst_format.sample_rate as i64 / sample_rate as i64) as usize);
+				}
+			}
+		}
+		self.next_pts = pts.map(|pts| pts + frame.nb_samples() as i64);
+	}
+
+	// Appends silence ahead of samples still held by the converter, its delay is a few samples at most
+	fn insert_silence(&mut self, count: usize) {
+		self.reserve(count);
+		fill_samples(&mut self.output, self.sample_count, count, from_normalized::<T>(0.));
+		self.commit_samples(count);
+	}
+
+	fn update_format(&mut self, frame: &Frame) -> Result<(), Error> {
+		let frame_format = frame.audio_format();
+		if frame_format == self.src_format {
+			return Ok(());
+		}
+
+		// Chained streams (e.g. Ogg) can switch parameters mid-stream, output keeps the initial format
+		if !self.options.allow_converter {
+			return Err(conversion_error(&frame_format, &self.dst_format));
+		}
+		if let Some(mut filter) = self.filter.take() {
+			filter.graph.push_flush()?;
+			self.drain_filter(&mut filter)?;
+			// Filters such as the pitch shift are parametrized by the sample rate
+			if let Some(description) = self.options.filter_description(frame_format.sample_rate) {
+				filter.graph = FilterGraph::new(&frame_format, &frame_format, &description)?;
+				self.filter = Some(filter);
+			}
+		}
+
+		self.converter = Some(create_converter(frame_format, self.dst_format, &self.options)?);
+		self.src_format = frame_format;
 		Ok(())
 	}
 
-	pub fn copy_frame_to_cursor(&mut self, frame: &mut Frame) -> Result<(), Error> {
-		match self.converter {
+	fn drain_filter(&mut self, filter: &mut ReaderFilter) -> Result<(), Error> {
+		while match filter.graph.pull(&mut filter.frame) {
+			Err(Error::FFM(FFError::Again)) => false,
+			Err(Error::FFM(FFError::Eof)) => false,
+			Err(e) => return Err(e),
+			_ => true
+		} {
+			self.report_silence(&filter.frame);
+			self.append_frame(&mut filter.frame)?;
+		}
+		Ok(())
+	}
+
+	// Accounts for `written` samples appended to the buffer, trimming those preceding a seek target
+	fn commit_samples(&mut self, written: usize) {
+		if let Some(factor) = self.int_scale() {
+			scale_samples(&mut self.output, self.sample_count, written, factor);
+		}
+
+		let position = self.position.unwrap_or(self.skip_to);
+		let skip = self.skip_to.saturating_sub(position).min(written);
+		if skip > 0 {
+			move_samples(&mut self.output, self.sample_count + skip, self.sample_count, written - skip);
+		}
+
+		self.sample_count += written - skip;
+		self.position = Some(position + written);
+	}
+
+	fn frame_position(&self, frame: &Frame) -> usize {
+		// AV_NOPTS_VALUE
+		if frame.pts() == i64::min_value() {
+			return self.skip_to;
+		}
+		pts_to_samples(frame.pts(), self.input.stream().time_base(), self.dst_format.sample_rate).max(0) as usize
+	}
+
+	fn int_scale(&self) -> Option<f64> {
+		let bits = match (T::scalar_type(), self.src_format.sample_format.sample_type()) {
+			(ScalarType::F32, Some(src)) | (ScalarType::F64, Some(src)) => match src {
+				ScalarType::U8 => 8,
+				ScalarType::I16 => 16,
+				ScalarType::I32 => 32,
+				ScalarType::I64 => 64,
+				_ => return None
+			},
+			_ => return None
+		};
+		self.options.int_scaling.factor(bits)
+	}
+
+	// Grows geometrically so estimates that fall short don't reallocate for every frame
+	fn buffer_silence(&mut self, count: usize) {
+		self.reserve(count);
+		fill_samples(&mut self.output, self.sample_count, count, from_normalized::<T>(0.));
+		self.sample_count += count;
+	}
+
+	fn reserve(&mut self, count: usize) {
+		let needed = self.sample_count + count;
+		if self.output.samples() < needed {
+			self.output.set_samples(needed.max(self.output.samples() * 2));
+		}
+	}
+
+	fn flush_converter(&mut self) -> Result<(), Error> {
+		let capacity = match self.converter {
+			None => return Ok(()),
+			Some(ref converter) => converter.flush_sample_count() as usize
+		};
+		self.reserve(capacity);
+
+		let buffer_size = self.output.samples() - self.sample_count;
+		self.cursor.storage_mut().storage_mut().shift_col_to(&mut self.output, self.sample_count, buffer_size);
+
+		let written = match self.converter {
+			Some(ref mut converter) => converter.flush_slice(&mut self.cursor)? as usize,
+			None => 0
+		};
+		self.commit_samples(written);
+		Ok(())
+	}
+
+	fn append_frame(&mut self, frame: &mut Frame) -> Result<(), Error> {
+		let capacity = match self.converter {
+			None => frame.nb_samples() as usize,
+			Some(ref converter) => converter.get_sample_count(frame.nb_samples()) as usize
+		};
+		self.reserve(capacity);
+
+		let buffer_size = self.output.samples() - self.sample_count;
+		self.cursor.storage_mut().storage_mut().shift_col_to(&mut self.output, self.sample_count, buffer_size);
+
+		let written = self.copy_frame_to_cursor(frame)? as usize;
+		self.commit_samples(written);
+		Ok(())
+	}
+
+	pub fn copy_frame_to_cursor(&mut self, frame: &mut Frame) -> Result<i32, Error> {
+		Ok(match self.converter {
 			None => {
 				match self.output.packing_type() {
 					SamplePacking::Interleaved => {
@@ -181,12 +742,132 @@ impl<'a, T, P, S> Reader<'a, T, P, S>
 						}
 					}
 				}
+				frame.nb_samples()
 			},
 			Some(ref mut converter) => {
-				converter.convert_frame(frame, &mut self.cursor)?;
+				let _span = timed_span!(TRACE, "convert", samples = frame.nb_samples());
+				converter.convert_frame(frame, &mut self.cursor)?
 			}
+		})
+	}
+}
+
+fn channel_map(src_fmt: &AudioFormat, dst_fmt: &AudioFormat, order: ChannelOrder) -> Option<&'static [i32]> {
+	let channels = dst_fmt.channels();
+	match src_fmt.channels() == channels {
+		true => order.permutation(channels as usize),
+		false => None
+	}
+}
+
+// A lavfi graph reading the mono streams of `format_ctx`, opened from `path`, merged into one. None with
+// fewer than two of them
+fn merge_mono_graph(path: &str, input_format: Option<&str>, format_ctx: &FormatContext) -> Option<String> {
+	let streams: Vec<i32> = format_ctx.streams().iter()
+		.filter(|s| s.parameters().is_audio() && s.parameters().channels() == 1)
+		.map(|s| s.index())
+		.collect();
+	if streams.len() < 2 {
+		return None;
+	}
+
+	let specifiers: Vec<String> = streams.iter().map(|s| s.to_string()).collect();
+	let labels: String = (0..streams.len()).map(|i| format!("[m{}]", i)).collect();
+	let format = input_format.map_or(String::new(), |f| format!(":f={}", escape_filter_option(f)));
+	Some(format!(
+		"amovie={}{}:streams={}{};{}amerge=inputs={}[out0]",
+		escape_filter_option(path), format, specifiers.join("+"), labels, labels, streams.len()
+	))
+}
+
+fn conversion_error(src_fmt: &AudioFormat, dst_fmt: &AudioFormat) -> Error {
+	Error::Unsupported(format!(
+		"Decoding to {} at {} Hz with {} channels requires converting from {} at {} Hz with {} channels",
+		dst_fmt.sample_format.name(), dst_fmt.sample_rate, dst_fmt.channels(),
+		src_fmt.sample_format.name(), src_fmt.sample_rate, src_fmt.channels()
+	))
+}
+
+fn create_converter(src_fmt: AudioFormat, dst_fmt: AudioFormat, options: &ReaderOptions) -> Result<Converter, Error> {
+	let mut converter = Converter::with_channel_map(src_fmt, dst_fmt, channel_map(&src_fmt, &dst_fmt, options.channel_order))?;
+	for (key, value) in &options.resampler_options {
+		converter.set_option(key, value)?;
+	}
+	if let Some(ref matrix) = options.mix_matrix {
+		let inputs = matrix.first().map_or(0, |r| r.len());
+		if inputs != src_fmt.channels() as usize || matrix.len() != dst_fmt.channels() as usize {
+			return Err(Error::from(format!(
+				"The mix matrix maps {} to {} channels, the input has {} and the output {}",
+				inputs, matrix.len(), src_fmt.channels(), dst_fmt.channels()
+			)));
 		}
-		Ok(())
+		converter.set_mix_matrix(matrix)?;
 	}
+	Ok(converter)
+}
+
+pub struct Windows<'a, T, P, S>
+	where T: Sample, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+{
+	reader: Reader<'a, T, P, S>,
+	size: usize,
+	hop: usize,
+	emitted: bool,
 }
 
+impl<'a, T, P, S> Iterator for Windows<'a, T, P, S>
+	where T: Sample, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+{
+	type Item = Result<AudioContainer<T, P, S>, Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		while self.reader.buffered_samples() < self.size {
+			match self.reader.decode_next() {
+				Err(e) => return Some(Err(e)),
+				Ok(false) => break,
+				Ok(true) => {}
+			}
+		}
+
+		// Past the end only emit windows holding samples not covered by the previous one
+		let overlap = match self.emitted {
+			true => self.size.saturating_sub(self.hop),
+			false => 0
+		};
+		if self.reader.buffered_samples() <= overlap {
+			return None;
+		}
+
+		self.emitted = true;
+		Some(Ok(self.reader.take_samples(self.size, self.hop)))
+	}
+}
+
+pub struct Chunks<'a, T, P, S>
+	where T: Sample, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+{
+	reader: Reader<'a, T, P, S>,
+	size: usize,
+}
+
+impl<'a, T, P, S> Iterator for Chunks<'a, T, P, S>
+	where T: Sample, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+{
+	type Item = Result<AudioContainer<T, P, S>, Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		while self.reader.buffered_samples() < self.size {
+			match self.reader.decode_next() {
+				Err(e) => return Some(Err(e)),
+				Ok(false) => break,
+				Ok(true) => {}
+			}
+		}
+
+		let count = self.size.min(self.reader.buffered_samples());
+		if count == 0 {
+			return None;
+		}
+		Some(Ok(self.reader.take_samples(count, count)))
+	}
+}
@@ -0,0 +1,82 @@
+use crate::ffmpeg::*;
+use crate::error::Error;
+use crate::mux::Muxer;
+use crate::options::WriterOptions;
+
+#[derive(Clone, Debug, Default)]
+pub struct RemuxOptions {
+	pub(crate) keep_other_streams: bool,
+	pub(crate) format: Option<String>,
+	pub(crate) metadata: Vec<(String, String)>,
+	pub(crate) bitstream_filter: Option<String>,
+}
+
+impl RemuxOptions {
+	pub fn new() -> Self { Self::default() }
+
+	// Also copies the video, subtitle, attachment and data streams instead of only the audio
+	pub fn keep_other_streams(mut self, keep_other_streams: bool) -> Self {
+		self.keep_other_streams = keep_other_streams;
+		self
+	}
+
+	pub fn format(mut self, format: &str) -> Self {
+		self.format = Some(format.to_string());
+		self
+	}
+
+	// Passes the audio packets through a bitstream filter, e.g. `aac_adtstoasc` when moving ADTS AAC into MP4
+	pub fn bitstream_filter(mut self, filter: &str) -> Self {
+		self.bitstream_filter = Some(filter.to_string());
+		self
+	}
+
+	// Overrides a container tag, the other tags of the input are kept
+	pub fn metadata(mut self, key: &str, value: &str) -> Self {
+		self.metadata.retain(|(k, _)| k != key);
+		self.metadata.push((key.to_string(), value.to_string()));
+		self
+	}
+}
+
+// Copies the streams of `input` to `output` without re-encoding, e.g. to retag or change the container
+pub fn remux(input: &str, output: &str, options: &RemuxOptions) -> Result<(), Error> {
+	let input_ctx = open_read(input)?;
+	let mut writer_options = WriterOptions::new();
+	writer_options.format = options.format.clone();
+	let mut muxer = Muxer::open_with(output, &writer_options)?;
+
+	muxer.format_ctx_mut().copy_metadata_from(&input_ctx)?;
+	for (key, value) in &options.metadata {
+		muxer.format_ctx_mut().set_metadata(key, value)?;
+	}
+
+	// Input stream index to the input time base and output stream index
+	let mut mapping = Vec::new();
+	for stream in input_ctx.streams() {
+		if !stream.parameters().is_audio() {
+			mapping.push(match options.keep_other_streams {
+				true => Some((stream.time_base(), muxer.add_stream_from(&stream, None)?)),
+				false => None
+			});
+			continue;
+		}
+
+		let filter = options.bitstream_filter.as_ref().map(|f| f.as_str());
+		mapping.push(Some((stream.time_base(), muxer.add_stream_from(&stream, filter)?)));
+	}
+
+	let mut packet = Packet::empty();
+	while match packet.read(&input_ctx) {
+		Err(Error::FFM(FFError::Eof)) => false,
+		Err(e) => return Err(e),
+		Ok(_) => true
+	} {
+		if let Some(Some((time_base, index))) = mapping.get(packet.stream_id() as usize) {
+			muxer.write_packet(&mut packet, *index, *time_base)?;
+		}
+		packet.reset();
+	}
+
+	muxer.finish()
+}
@@ -0,0 +1,164 @@
+use litaudio::*;
+use litcontainers::*;
+
+pub fn to_normalized<T: Sample>(v: T) -> f64 {
+	let v = to_f64(v);
+	match T::scalar_type() {
+		ScalarType::U8 => (v - 128.) / 128.,
+		ScalarType::I16 => v / 32768.,
+		ScalarType::I32 => v / 2147483648.,
+		ScalarType::I64 => v / 9223372036854775808.,
+		_ => v,
+	}
+}
+
+pub fn from_normalized<T: Sample>(v: f64) -> T {
+	let v = match T::scalar_type() {
+		ScalarType::U8 => (v * 128. + 128.).round().max(0.).min(255.),
+		ScalarType::I16 => (v * 32768.).round().max(-32768.).min(32767.),
+		ScalarType::I32 => (v * 2147483648.).round().max(-2147483648.).min(2147483647.),
+		// The upper bound is the largest f64 below 2^63, which still converts to i64
+		ScalarType::I64 => (v * 9223372036854775808.).round().max(-9223372036854775808.).min(9223372036854774784.),
+		_ => v,
+	};
+	from_f64(v)
+}
+
+// Dispatches on the scalar type, so the helpers only need `T: Sample` like the readers and writers
+fn to_f64<T: Sample>(v: T) -> f64 {
+	let ptr = &v as *const T;
+	unsafe {
+		match T::scalar_type() {
+			ScalarType::U8 => *(ptr as *const u8) as f64,
+			ScalarType::I16 => *(ptr as *const i16) as f64,
+			ScalarType::I32 => *(ptr as *const i32) as f64,
+			ScalarType::I64 => *(ptr as *const i64) as f64,
+			ScalarType::F32 => *(ptr as *const f32) as f64,
+			ScalarType::F64 => *(ptr as *const f64),
+			_ => 0.,
+		}
+	}
+}
+
+fn from_f64<T: Sample>(v: f64) -> T {
+	let mut out = T::default();
+	let ptr = &mut out as *mut T;
+	unsafe {
+		match T::scalar_type() {
+			ScalarType::U8 => *(ptr as *mut u8) = v as u8,
+			ScalarType::I16 => *(ptr as *mut i16) = v as i16,
+			ScalarType::I32 => *(ptr as *mut i32) = v as i32,
+			ScalarType::I64 => *(ptr as *mut i64) = v as i64,
+			ScalarType::F32 => *(ptr as *mut f32) = v as f32,
+			ScalarType::F64 => *(ptr as *mut f64) = v,
+			_ => {}
+		}
+	}
+	out
+}
+
+pub fn channel_iter<'a, T, P, S>(s: &'a S, channel: usize) -> impl Iterator<Item=T> + 'a
+	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P>
+{
+	let ptr = s.as_row_ptr(channel);
+	let stride = s.col_stride();
+	(0..s.samples()).map(move |i| unsafe { *ptr.add(i * stride) })
+}
+
+pub fn map_channel<T, P, S, F>(s: &mut S, channel: usize, mut f: F)
+	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P> + StorageMut<T>, F: FnMut(usize, T) -> T
+{
+	let ptr = s.as_row_ptr_mut(channel);
+	let stride = s.col_stride();
+	for i in 0..s.samples() {
+		unsafe {
+			let v = ptr.add(i * stride);
+			*v = f(i, *v);
+		}
+	}
+}
+
+pub fn copy_samples<T, P, S, SD>(src: &S, src_offset: usize, dst: &mut SD, dst_offset: usize, count: usize)
+	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P>, SD: AudioStorage<T, P> + StorageMut<T>
+{
+	let (src_stride, dst_stride) = (src.col_stride(), dst.col_stride());
+	for c in 0..src.channels().min(dst.channels()) {
+		let src_ptr = src.as_row_ptr(c);
+		let dst_ptr = dst.as_row_ptr_mut(c);
+		for i in 0..count {
+			unsafe { *dst_ptr.add((dst_offset + i) * dst_stride) = *src_ptr.add((src_offset + i) * src_stride); }
+		}
+	}
+}
+
+// Moves the samples in [offset, offset + count) to the start of each channel
+pub fn shift_left<T, P, S>(s: &mut S, offset: usize, count: usize)
+	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P> + StorageMut<T>
+{
+	move_samples(s, offset, 0, count)
+}
+
+// Moves `count` samples from `src_offset` to an earlier `dst_offset` within each channel
+pub fn move_samples<T, P, S>(s: &mut S, src_offset: usize, dst_offset: usize, count: usize)
+	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P> + StorageMut<T>
+{
+	let stride = s.col_stride();
+	for c in 0..s.channels() {
+		let ptr = s.as_row_ptr_mut(c);
+		for i in 0..count {
+			unsafe { *ptr.add((i + dst_offset) * stride) = *ptr.add((i + src_offset) * stride); }
+		}
+	}
+}
+
+pub fn fill_samples<T, P, S>(s: &mut S, offset: usize, count: usize, value: T)
+	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P> + StorageMut<T>
+{
+	let stride = s.col_stride();
+	for c in 0..s.channels() {
+		let ptr = s.as_row_ptr_mut(c);
+		for i in offset..offset + count {
+			unsafe { *ptr.add(i * stride) = value; }
+		}
+	}
+}
+
+// Multiplies float samples in [offset, offset + count) by `factor`, integer samples are left untouched
+pub fn scale_samples<T, P, S>(s: &mut S, offset: usize, count: usize, factor: f64)
+	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P> + StorageMut<T>
+{
+	let stride = s.col_stride();
+	for c in 0..s.channels() {
+		let ptr = s.as_row_ptr_mut(c);
+		for i in offset..offset + count {
+			unsafe {
+				match T::scalar_type() {
+					ScalarType::F32 => *(ptr.add(i * stride) as *mut f32) *= factor as f32,
+					ScalarType::F64 => *(ptr.add(i * stride) as *mut f64) *= factor,
+					_ => return,
+				}
+			}
+		}
+	}
+}
+
+// Largest magnitude in [offset, offset + count) relative to full scale
+pub fn peak_level<T, P, S>(s: &S, offset: usize, count: usize) -> f64
+	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P>
+{
+	(0..s.channels())
+		.flat_map(|c| channel_iter(s, c).skip(offset).take(count))
+		.fold(0f64, |peak, v| peak.max(to_normalized(v).abs()))
+}
+
+// Multiplies the samples in [offset, offset + count) by `factor`, clipping integer samples to their range
+pub fn apply_gain<T, P, S>(s: &mut S, offset: usize, count: usize, factor: f64)
+	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P> + StorageMut<T>
+{
+	for c in 0..s.channels() {
+		map_channel(s, c, |i, v| match i >= offset && i < offset + count {
+			true => from_normalized(to_normalized(v) * factor),
+			false => v
+		});
+	}
+}
@@ -0,0 +1,358 @@
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Sender, SyncSender, Receiver, TryRecvError};
+use std::thread;
+use std::path::Path;
+use std::time::Duration;
+use litaudio::*;
+use litcontainers::*;
+use crate::error::Error;
+use crate::options::ReaderOptions;
+use crate::reader::Reader;
+use crate::loops::*;
+use crate::time::seconds_to_samples;
+
+const BUFFER_DURATION: f64 = 2.;
+const BLOCK_SIZE: usize = 1024;
+
+enum Command {
+	Seek(Duration),
+}
+
+// Single producer single consumer ring, the counters only grow and wrap around the buffer length
+struct Ring<T> {
+	buffer: UnsafeCell<Vec<T>>,
+	read: AtomicUsize,
+	write: AtomicUsize,
+	// Samples before this position predate a seek and are skipped by the consumer
+	discard: AtomicUsize,
+}
+
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+impl<T: Sample> Ring<T> {
+	fn new(capacity: usize) -> Self {
+		Ring {
+			buffer: UnsafeCell::new(vec![T::default(); capacity.max(1)]),
+			read: AtomicUsize::new(0),
+			write: AtomicUsize::new(0),
+			discard: AtomicUsize::new(0),
+		}
+	}
+
+	fn capacity(&self) -> usize { unsafe { (*self.buffer.get()).len() } }
+
+	fn len(&self) -> usize {
+		let write = self.write.load(Ordering::Acquire);
+		write - self.read.load(Ordering::Acquire).max(self.discard.load(Ordering::Acquire)).min(write)
+	}
+
+	fn push(&self, data: &[T]) -> usize {
+		let (read, write) = (self.read.load(Ordering::Acquire), self.write.load(Ordering::Relaxed));
+		let count = data.len().min(self.capacity() - (write - read));
+		let buffer = unsafe { &mut *self.buffer.get() };
+		for (i, v) in data[..count].iter().enumerate() {
+			buffer[(write + i) % buffer.len()] = *v;
+		}
+		self.write.store(write + count, Ordering::Release);
+		count
+	}
+
+	fn pop(&self, out: &mut [T]) -> usize {
+		let write = self.write.load(Ordering::Acquire);
+		let read = self.read.load(Ordering::Relaxed).max(self.discard.load(Ordering::Acquire)).min(write);
+		let count = out.len().min(write - read);
+		let buffer = unsafe { &*self.buffer.get() };
+		for (i, v) in out[..count].iter_mut().enumerate() {
+			*v = buffer[(read + i) % buffer.len()];
+		}
+		self.read.store(read + count, Ordering::Release);
+		count
+	}
+
+	// Called by the producer, the consumer drops everything written so far
+	fn discard_written(&self) {
+		self.discard.store(self.write.load(Ordering::Relaxed), Ordering::Release);
+	}
+}
+
+// Streams a file through a preallocated buffer that is refilled on a decoder thread, `fill` never allocates or blocks
+pub struct StreamedSound<T, P, S>
+	where T: Sample, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+{
+	ring: Arc<Ring<T>>,
+	commands: Sender<Command>,
+	looping: Arc<AtomicBool>,
+	finished: Arc<AtomicBool>,
+	error: Arc<Mutex<Option<Error>>>,
+	channels: usize,
+	sample_rate: i32,
+	loop_points: Option<LoopPoints>,
+	_phantom: PhantomData<(P, S)>,
+}
+
+impl<T, P, S> StreamedSound<T, P, S>
+	where T: Sample + Send + 'static, P: SamplePackingType + Send + 'static,
+	      S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T> + Send + 'static
+{
+	pub fn open(path: &str, options: ReaderOptions) -> Result<Self, Error> {
+		let (opened_sender, opened) = sync_channel(1);
+		let (commands, command_receiver) = channel();
+		let looping = Arc::new(AtomicBool::new(false));
+		let finished = Arc::new(AtomicBool::new(false));
+		let error = Arc::new(Mutex::new(None));
+
+		let loop_points = read_loop_points(Path::new(path)).unwrap_or(None);
+
+		let path = path.to_string();
+		let (thread_looping, thread_finished, thread_error) = (looping.clone(), finished.clone(), error.clone());
+		thread::spawn(move || {
+			let reader = match Reader::<T, P, S>::open_with(&path, options) {
+				Err(e) => return opened_sender.send(Err(e)).unwrap_or(()),
+				Ok(reader) if reader.channels() == 0 => {
+					return opened_sender.send(Err(Error::from("The input has no channels"))).unwrap_or(())
+				},
+				Ok(reader) => reader
+			};
+			let ring = Arc::new(Ring::new(
+				(BUFFER_DURATION * reader.sample_rate() as f64) as usize * reader.channels()
+			));
+			let loop_points = loop_points.map(|p| p.rescale(reader.info().sample_rate, reader.sample_rate()));
+			if opened_sender.send(Ok((ring.clone(), reader.channels(), reader.sample_rate(), loop_points))).is_err() {
+				return;
+			}
+			if let Err(e) = decode_loop(reader, &ring, loop_points, &command_receiver, &thread_looping, &thread_finished) {
+				*thread_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(e);
+			}
+			thread_finished.store(true, Ordering::Release);
+		});
+
+		let (ring, channels, sample_rate, loop_points) = opened.recv()
+			.map_err(|_| Error::from("The decoder thread exited"))??;
+		Ok(StreamedSound {
+			ring, commands, looping, finished, error, channels, sample_rate, loop_points, _phantom: PhantomData
+		})
+	}
+
+	// Waits until `seconds` of audio are buffered (at most the buffer size) or the end is reached
+	pub fn preload(&self, seconds: f64) {
+		let target = (seconds_to_samples(seconds, self.sample_rate).max(0) as usize * self.channels).min(self.ring.capacity());
+		while self.ring.len() < target && !self.finished.load(Ordering::Acquire) {
+			thread::sleep(Duration::from_millis(1));
+		}
+	}
+
+	// Copies interleaved samples into `out`, returns fewer than requested on an underrun or at the end.
+	// A decode or seek error is returned once, after the samples decoded before it
+	pub fn fill(&self, out: &mut [T]) -> Result<usize, Error> {
+		let count = out.len() - out.len() % self.channels;
+		match self.ring.pop(&mut out[..count]) {
+			// Not waiting on the decoder thread, an error it is storing is returned by the next call
+			0 => match self.error.try_lock().ok().and_then(|mut e| e.take()) {
+				Some(e) => Err(e),
+				None => Ok(0)
+			},
+			count => Ok(count)
+		}
+	}
+
+	// Samples buffered before the seek completes are skipped, `fill` returns nothing until new ones arrive
+	pub fn seek(&self, position: Duration) -> Result<(), Error> {
+		self.finished.store(false, Ordering::Release);
+		self.commands.send(Command::Seek(position)).map_err(|_| Error::from("The decoder thread exited"))
+	}
+
+	// Jumps from the loop end back to the loop start, or from the end to the beginning without loop points
+	pub fn looping(&self, looping: bool) {
+		self.looping.store(looping, Ordering::Release);
+	}
+
+	pub fn is_finished(&self) -> bool {
+		self.finished.load(Ordering::Acquire) && self.ring.len() == 0
+	}
+
+	// The sustain loop of a WAV `smpl` chunk in the output sample rate
+	pub fn loop_points(&self) -> Option<LoopPoints> { self.loop_points }
+
+	pub fn channels(&self) -> usize { self.channels }
+
+	pub fn sample_rate(&self) -> i32 { self.sample_rate }
+}
+
+fn decode_loop<T, P, S>(
+	mut reader: Reader<T, P, S>, ring: &Ring<T>, loop_points: Option<LoopPoints>, commands: &Receiver<Command>,
+	looping: &AtomicBool, finished: &AtomicBool
+) -> Result<(), Error>
+	where T: Sample, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+{
+	let mut block = vec![T::default(); BLOCK_SIZE * reader.channels()];
+	let (mut offset, mut len) = (0, 0);
+
+	loop {
+		match commands.try_recv() {
+			Ok(Command::Seek(position)) => {
+				reader.seek(position)?;
+				ring.discard_written();
+				finished.store(false, Ordering::Release);
+				offset = 0;
+				len = 0;
+			},
+			Err(TryRecvError::Disconnected) => return Ok(()),
+			Err(TryRecvError::Empty) => {}
+		}
+
+		if len == offset {
+			let region = loop_points.filter(|p| !p.is_empty() && looping.load(Ordering::Acquire));
+			let start = reader.position();
+
+			// Blocks stop at the loop end, the queued seek then trims to the exact loop start
+			let mut size = BLOCK_SIZE;
+			if let Some(points) = region.filter(|p| start < p.end) {
+				size = size.min(points.end - start);
+			}
+
+			buffer_samples(&mut reader, size)?;
+			len = reader.take_interleaved(&mut block[..size * reader.channels()]) * reader.channels();
+			offset = 0;
+
+			if let Some(points) = region.filter(|p| start < p.end && reader.position() >= p.end) {
+				reader.seek_sample(points.start)?;
+			}
+
+			if len == 0 {
+				let restart = region.map_or(0, |p| p.start);
+				if looping.load(Ordering::Acquire) && reader.seek_sample(restart).is_ok() {
+					continue;
+				}
+				finished.store(true, Ordering::Release);
+				thread::sleep(Duration::from_millis(10));
+				continue;
+			}
+		}
+
+		offset += ring.push(&block[offset..len]);
+		if offset < len {
+			thread::sleep(Duration::from_millis(1));
+		}
+	}
+}
+
+// Decodes until `size` samples are buffered or the input ends
+fn buffer_samples<T, P, S>(reader: &mut Reader<T, P, S>, size: usize) -> Result<(), Error>
+	where T: Sample, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+{
+	while reader.buffered_samples() < size && reader.decode_next()? {}
+	Ok(())
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Swap {
+	// The next block is now current
+	Block,
+	// The next block wasn't decoded in time, the current block is kept
+	Underrun,
+	Finished,
+}
+
+// Two blocks of interleaved samples, the next one is decoded on a helper thread while the current one is played
+pub struct DoubleBuffered<T, P, S>
+	where T: Sample, P: SamplePackingType, S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T>
+{
+	current: Vec<T>,
+	len: usize,
+	filled: Receiver<Result<(Vec<T>, usize), Error>>,
+	recycle: SyncSender<Vec<T>>,
+	underruns: usize,
+	finished: bool,
+	channels: usize,
+	sample_rate: i32,
+	_phantom: PhantomData<(P, S)>,
+}
+
+impl<T, P, S> DoubleBuffered<T, P, S>
+	where T: Sample + Send + 'static, P: SamplePackingType + Send + 'static,
+	      S: StorageMut<T> + DynamicSampleStorage<T> + StorageConstructor<T> + Send + 'static
+{
+	// Blocks hold `block_size` samples per channel
+	pub fn open(path: &str, options: ReaderOptions, block_size: usize) -> Result<Self, Error> {
+		let (opened_sender, opened) = sync_channel(1);
+		let (filled_sender, filled) = sync_channel(1);
+		let (recycle, recycled) = sync_channel::<Vec<T>>(1);
+
+		let path = path.to_string();
+		thread::spawn(move || {
+			let mut reader = match Reader::<T, P, S>::open_with(&path, options) {
+				Err(e) => return opened_sender.send(Err(e)).unwrap_or(()),
+				Ok(reader) => reader
+			};
+			if opened_sender.send(Ok((reader.channels(), reader.sample_rate()))).is_err() {
+				return;
+			}
+
+			let mut block = vec![T::default(); block_size * reader.channels()];
+			loop {
+				if let Err(e) = buffer_samples(&mut reader, block_size) {
+					filled_sender.send(Err(e)).unwrap_or(());
+					break;
+				}
+				let len = reader.take_interleaved(&mut block) * reader.channels();
+				if len == 0 || filled_sender.send(Ok((block, len))).is_err() {
+					break;
+				}
+				block = match recycled.recv() {
+					Err(_) => break,
+					Ok(block) => block
+				};
+			}
+		});
+
+		let (channels, sample_rate) = opened.recv().map_err(|_| Error::from("The decoder thread exited"))??;
+		Ok(DoubleBuffered {
+			current: vec![T::default(); block_size * channels], len: 0, filled, recycle, underruns: 0,
+			finished: false, channels, sample_rate, _phantom: PhantomData
+		})
+	}
+
+	// Never blocks, an underrun is counted when the next block isn't ready yet. A decode error ends the
+	// stream after the blocks decoded before it
+	pub fn swap(&mut self) -> Result<Swap, Error> {
+		if self.finished {
+			return Ok(Swap::Finished);
+		}
+
+		Ok(match self.filled.try_recv() {
+			Ok(Err(e)) => {
+				self.finished = true;
+				self.len = 0;
+				return Err(e);
+			},
+			Ok(Ok((block, len))) => {
+				let previous = std::mem::replace(&mut self.current, block);
+				self.len = len;
+				// The helper thread is waiting for it, so this doesn't block
+				let _ = self.recycle.try_send(previous);
+				Swap::Block
+			},
+			Err(TryRecvError::Empty) => {
+				self.underruns += 1;
+				Swap::Underrun
+			},
+			Err(TryRecvError::Disconnected) => {
+				self.finished = true;
+				self.len = 0;
+				Swap::Finished
+			}
+		})
+	}
+
+	// The current interleaved block, the last one may be shorter
+	pub fn block(&self) -> &[T] { &self.current[..self.len] }
+
+	pub fn underruns(&self) -> usize { self.underruns }
+
+	pub fn channels(&self) -> usize { self.channels }
+
+	pub fn sample_rate(&self) -> i32 { self.sample_rate }
+}
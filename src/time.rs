@@ -0,0 +1,32 @@
+use std::time::Duration;
+use crate::ffmpeg::Rational;
+
+// Sample index of `pts` in `time_base`, rounded to the nearest sample
+pub fn pts_to_samples(pts: i64, time_base: Rational, sample_rate: i32) -> i64 {
+	time_base.rescale(pts, Rational::samples(sample_rate))
+}
+
+pub fn samples_to_pts(samples: i64, sample_rate: i32, time_base: Rational) -> i64 {
+	Rational::samples(sample_rate).rescale(samples, time_base)
+}
+
+pub fn pts_to_seconds(pts: i64, time_base: Rational) -> f64 {
+	pts as f64 * time_base.to_f64()
+}
+
+pub fn seconds_to_samples(seconds: f64, sample_rate: i32) -> i64 {
+	(seconds * sample_rate as f64).round() as i64
+}
+
+pub fn samples_to_seconds(samples: i64, sample_rate: i32) -> f64 {
+	samples as f64 / sample_rate as f64
+}
+
+// Rounded down, so the sample at the returned index starts at or before `duration`
+pub fn duration_to_samples(duration: Duration, sample_rate: i32) -> usize {
+	(duration.as_nanos() * sample_rate as u128 / 1_000_000_000) as usize
+}
+
+pub fn samples_to_duration(samples: usize, sample_rate: i32) -> Duration {
+	Duration::from_nanos((samples as u128 * 1_000_000_000 / sample_rate.max(1) as u128) as u64)
+}
@@ -0,0 +1,60 @@
+use std::fmt;
+
+// SMPTE timecode, `drop_frame` marks 29.97/59.94 drop frame counting
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Timecode {
+	pub hours: u32,
+	pub minutes: u32,
+	pub seconds: u32,
+	pub frames: u32,
+	pub drop_frame: bool,
+}
+
+impl Timecode {
+	pub fn new(hours: u32, minutes: u32, seconds: u32, frames: u32) -> Self {
+		Timecode { hours, minutes, seconds, frames, drop_frame: false }
+	}
+
+	// Parses `HH:MM:SS:FF`, a `;` or `.` before the frames denotes drop frame
+	pub fn parse(s: &str) -> Option<Self> {
+		let drop_frame = s.contains(';') || s.contains('.');
+		let parts: Vec<u32> = s.split(|c| c == ':' || c == ';' || c == '.')
+			.map(|p| p.trim().parse().ok())
+			.collect::<Option<_>>()?;
+		match parts.as_slice() {
+			[hours, minutes, seconds, frames] => Some(Timecode {
+				hours: *hours, minutes: *minutes, seconds: *seconds, frames: *frames, drop_frame
+			}),
+			_ => None
+		}
+	}
+
+	// Timecode of a sample offset since midnight, such as the BWF time reference, in non drop frame counting
+	pub fn from_samples(samples: u64, sample_rate: i32, fps: u32) -> Self {
+		let sample_rate = sample_rate.max(1) as u64;
+		let seconds = samples / sample_rate;
+		Timecode {
+			hours: (seconds / 3600 % 24) as u32,
+			minutes: (seconds / 60 % 60) as u32,
+			seconds: (seconds % 60) as u32,
+			frames: ((samples % sample_rate) * fps as u64 / sample_rate) as u32,
+			drop_frame: false,
+		}
+	}
+
+	// Sample offset since midnight in non drop frame counting
+	pub fn to_samples(&self, sample_rate: i32, fps: u32) -> u64 {
+		let seconds = self.hours as u64 * 3600 + self.minutes as u64 * 60 + self.seconds as u64;
+		seconds * sample_rate as u64 + self.frames as u64 * sample_rate as u64 / fps.max(1) as u64
+	}
+}
+
+impl fmt::Display for Timecode {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let separator = match self.drop_frame {
+			true => ';',
+			false => ':'
+		};
+		write!(f, "{:02}:{:02}:{:02}{}{:02}", self.hours, self.minutes, self.seconds, separator, self.frames)
+	}
+}
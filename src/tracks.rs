@@ -0,0 +1,128 @@
+use std::cmp;
+use std::marker::PhantomData;
+use litaudio::*;
+use litcontainers::*;
+use crate::ffmpeg::*;
+use crate::error::Error;
+use crate::options::WriterOptions;
+use crate::codec::Encoder;
+use crate::mux::Muxer;
+use crate::writer::SampleQueue;
+
+// Codec of `write_tracks` when the options name none
+const DEFAULT_CODEC: &str = "flac";
+// Input samples per channel encoded for a track before moving on to the next one
+const BLOCK_SIZE: usize = 4096;
+
+// An audio track of a multi-track file
+pub struct Track<'a, S> {
+	pub audio: &'a S,
+	// ISO 639-2 language tag such as "eng"
+	pub language: Option<String>,
+	pub title: Option<String>,
+}
+
+impl<'a, S> Track<'a, S> {
+	pub fn new(audio: &'a S) -> Self { Track { audio, language: None, title: None } }
+
+	pub fn language(mut self, language: &str) -> Self {
+		self.language = Some(language.to_string());
+		self
+	}
+
+	pub fn title(mut self, title: &str) -> Self {
+		self.title = Some(title.to_string());
+		self
+	}
+}
+
+// Encodes every track into its own audio stream of one file, such as Matroska audio (.mka) holding a track
+// per language. Uses the codec, chapters, attachments and muxer options of `options`, FLAC by default
+pub fn write_tracks<T, P, S>(path: &str, tracks: &[Track<S>], options: WriterOptions) -> Result<(), Error>
+	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P>
+{
+	let codec = options.codec.clone().unwrap_or_else(|| DEFAULT_CODEC.to_string());
+	let mut muxer = Muxer::open_with(path, &options)?;
+
+	let mut encoders = Vec::with_capacity(tracks.len());
+	for track in tracks {
+		let source = AudioFormat::from_storage(track.audio);
+		let encoder = Encoder::open_for(&codec, source, options.strict_sample_rate)?;
+		let index = muxer.add_encoder_stream(&encoder)?;
+		if let Some(ref language) = track.language {
+			muxer.set_stream_metadata(index, "language", language)?;
+		}
+		if let Some(ref title) = track.title {
+			muxer.set_stream_metadata(index, "title", title)?;
+		}
+		encoders.push(TrackEncoder::<T, P, S>::new(track.audio, encoder, index)?);
+	}
+
+	// The tracks advance a block at a time in turn, so their packets are interleaved
+	while encoders.iter().any(|e| !e.finished) {
+		for encoder in encoders.iter_mut().filter(|e| !e.finished) {
+			encoder.encode_block(&mut muxer)?;
+		}
+	}
+	muxer.finish()
+}
+
+struct TrackEncoder<'a, T, P, S> {
+	audio: &'a S,
+	encoder: Encoder,
+	queue: SampleQueue,
+	frame: Frame,
+	index: i32,
+	offset: usize,
+	finished: bool,
+	_phantoms: PhantomData<(T, P)>,
+}
+
+impl<'a, T, P, S> TrackEncoder<'a, T, P, S>
+	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P>
+{
+	fn new(audio: &'a S, encoder: Encoder, index: i32) -> Result<Self, Error> {
+		let queue = SampleQueue::new(AudioFormat::from_storage(audio), encoder.context(), None)?;
+		let frame = Frame::alloc(queue.format(), queue.frame_size())?;
+		Ok(TrackEncoder { audio, encoder, queue, frame, index, offset: 0, finished: false, _phantoms: PhantomData })
+	}
+
+	fn encode_block(&mut self, muxer: &mut Muxer) -> Result<(), Error> {
+		let count = cmp::min(BLOCK_SIZE, self.audio.samples() - self.offset);
+		let finish = count == 0;
+		match finish {
+			false => {
+				let planes: Vec<*const u8> = (0..planes::<T, P, S>(self.audio))
+					.map(|p| unsafe { self.audio.as_row_ptr(p).add(self.offset * self.audio.col_stride()) as *const u8 })
+					.collect();
+				unsafe { self.queue.push(planes.as_ptr(), count as i32)? };
+				self.offset += count;
+			},
+			true => self.queue.drain()?
+		}
+
+		let mut packets = Vec::new();
+		while self.queue.pop(&mut self.frame, finish)? {
+			packets.extend(self.encoder.encode(&mut self.frame)?);
+		}
+		if finish {
+			packets.extend(self.encoder.flush()?);
+			self.finished = true;
+		}
+
+		let time_base = self.encoder.time_base();
+		for mut packet in packets {
+			muxer.write_packet(&mut packet, self.index, time_base)?;
+		}
+		Ok(())
+	}
+}
+
+fn planes<T, P, S>(audio: &S) -> usize
+	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P>
+{
+	match audio.packing_type() {
+		SamplePacking::Interleaved => 1,
+		SamplePacking::Deinterleaved => audio.channels(),
+	}
+}
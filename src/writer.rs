@@ -3,11 +3,18 @@ use crate::sys::*;
 use crate::ffmpeg::*;
 use crate::error::Error;
 use crate::output::Output;
+use crate::options::WriterOptions;
+use crate::bwf::*;
+use std::path::Path;
 use litaudio::*;
 use litcontainers::*;
 use std::cmp::min;
 use std::marker::PhantomData;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread::{self, JoinHandle};
 
+// Encodes a whole container into a file, the encoder is chosen from the extension unless the options name
+// one and the samples are converted to a format it takes
 pub struct Writer<'a: 'b, 'b, T, P, S>
 	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P>
 {
@@ -16,7 +23,14 @@ pub struct Writer<'a: 'b, 'b, T, P, S>
 	cursor: Slice<'b, T, S::Rows, S::RowStride, Dynamic, S::ColStride>,
 	converter: Option<Converter>,
 	sample_count: usize,
+	// Samples encoded so far when the input is resampled to a rate the encoder supports
+	resampled: Option<usize>,
+	pts_offset: i64,
 	max_frame_size: usize,
+	loop_preroll: usize,
+	muxer_options: Vec<(String, String)>,
+	path: String,
+	bext_loudness: Option<BextLoudness>,
 	_phantoms: PhantomData<(P)>
 }
 
@@ -24,18 +38,62 @@ impl<'a: 'b, 'b, T, P, S> Writer<'a, 'b, T, P, S>
 	where T: Sample, P: SamplePackingType, S: AudioStorage<T, P>
 {
 	pub fn open(path: &str, audio: &'a S) -> Result<Self, Error> {
-		let output = Output::open(
+		Self::open_with(path, audio, WriterOptions::new())
+	}
+
+	pub fn open_with(path: &str, audio: &'a S, options: WriterOptions) -> Result<Self, Error> {
+		if let Some(order) = options.ambisonic_order {
+			if ((order + 1).pow(2) as usize) != audio.rows() {
+				return Err(Error::Unsupported(format!(
+					"Order {} ambisonics has {} channels, got {}", order, (order + 1).pow(2), audio.rows()
+				)));
+			}
+		}
+
+		let output = Output::open_with(
 			&path,
+			&options,
 			|i| pick_best_format(i, SampleFormat::from_type::<T, P>()),
-			ChannelLayout::default(audio.rows() as i32),
+			match options.ambisonic_order {
+				None => ChannelLayout::default(audio.rows() as i32),
+				Some(_) => ChannelLayout::empty()
+			},
+			audio.rows() as i32,
 			audio.sample_rate()
 		)?;
 		output.format_ctx().dump(&path);
 
-		let use_converter = output.sample_format() != SampleFormat::from_type::<T, P>();
+		let format_name = output.format_ctx().output_format_name().unwrap_or_default();
+		// Other muxers have no edit lists, so players would play the preroll before the loop start
+		if options.loop_preroll.is_some() && !["mp4", "mov", "ipod"].contains(&format_name.as_str()) {
+			return Err(Error::Unsupported(format!("Seamless loops need an edit list, {} has none", format_name)));
+		}
+		// The loudness fields are patched into the bext chunk of the finished WAV file
+		if options.bext_loudness.is_some() && format_name != "wav" {
+			return Err(Error::Unsupported(format!("Bext loudness needs a WAV output, got {}", format_name)));
+		}
+		if options.bext_loudness.is_some() && path.contains("://") {
+			return Err(Error::Unsupported("Bext loudness can only be written to local files".to_string()));
+		}
+
+		let resampled = match output.sample_rate() == audio.sample_rate() {
+			true => None,
+			false if options.loop_preroll.is_some() => return Err(Error::Unsupported(format!(
+				"Seamless loops can't be resampled, the encoder doesn't support {} Hz", audio.sample_rate()
+			))),
+			false => Some(0),
+		};
+
+		// The encoder takes the native order, each of its channels is fed from the reordered input
+		let channel_map = options.channel_order.and_then(|o| o.inverse_permutation(audio.rows()));
+		// Only interleaving or deinterleaving is left to the copy in `copy_cursor_to_frame`
+		let use_converter = output.sample_format().packed() != SampleFormat::from_type::<T, P>().packed()
+			|| channel_map.is_some() || resampled.is_some();
 		let converter = match use_converter {
 			false => None,
-			true => Some(output.converter(AudioFormat::from_storage(audio))?)
+			true => Some(output.converter_with_map(
+				AudioFormat::from_storage(audio), channel_map.as_ref().map(|m| m.as_slice())
+			)?)
 		};
 
 		let cursor = SliceBase::new(
@@ -49,33 +107,57 @@ impl<'a: 'b, 'b, T, P, S> Writer<'a, 'b, T, P, S>
 		).into();
 
 		let max_frame_size = output.frame_size();
-		Ok(Writer { output, input: audio, cursor, converter, sample_count: 0, max_frame_size, _phantoms: PhantomData })
+		let loop_preroll = options.loop_preroll.map_or(0, |p| p.min(audio.samples()) / max_frame_size * max_frame_size);
+		Ok(Writer {
+			output, input: audio, cursor, converter, sample_count: 0, resampled, pts_offset: 0, max_frame_size, loop_preroll,
+			muxer_options: options.muxer_options, path: path.to_string(), bext_loudness: options.bext_loudness,
+			_phantoms: PhantomData
+		})
 	}
 
+	pub fn sdp(&self) -> Result<String, Error> { self.output.format_ctx().sdp() }
+
 	pub fn write(mut self) -> Result<(), Error> {
-		unsafe { ffm_op!(avformat_write_header(self.output.format_ctx().as_mut_ptr(), ptr::null_mut()))?; }
+		self.output.format_ctx().write_header(&mut Dictionary::from_pairs(&self.muxer_options)?)?;
 
 		let mut frame = self.output.new_frame()?;
 		let mut packet = Packet::empty();
 
+		if self.loop_preroll > 0 {
+			// Encode the loop tail first, ending right before the first sample
+			self.sample_count = self.input.samples() - self.loop_preroll;
+			self.pts_offset = -(self.input.samples() as i64);
+			while match self.write_frame(&mut packet, Some(&mut frame)) {
+				Err(Error::FFM(FFError::Eof)) => false,
+				Err(e) => return Err(e),
+				Ok(_) => true
+			} {}
+			self.sample_count = 0;
+			self.pts_offset = 0;
+		}
+
 		while match self.write_frame(&mut packet, Some(&mut frame)) {
 			Err(Error::FFM(FFError::Eof)) => false,
 			Err(e) => return Err(e),
 			Ok(_) => true
 		} {}
 
-		while match self.write_frame(&mut packet, None) {
-			Err(Error::FFM(FFError::Eof)) => false,
-			Err(e) => return Err(e),
-			Ok(_) => true
-		} {}
+		self.write_frame(&mut packet, None)?;
 
 		unsafe { ffm_op!(av_write_trailer(self.output.format_ctx().as_mut_ptr()))? };
 
+		if let Some(loudness) = self.bext_loudness {
+			// The file has to be complete and closed before it can be patched
+			let path = self.path.clone();
+			drop(self);
+			write_loudness(Path::new(&path), &loudness)?;
+		}
+
 		Ok(())
 	}
 
 	pub fn write_frame(&mut self, packet: &mut Packet, frame: Option<&mut Frame>) -> Result<(), Error> {
+		let _span = timed_span!(TRACE, "encode", pts = self.sample_count as u64);
 		let mut frame_cap = 0;
 
 		match frame {
@@ -89,13 +171,26 @@ impl<'a: 'b, 'b, T, P, S> Writer<'a, 'b, T, P, S>
 			Some(frame) => {
 				// TODO: fill frame fn?
 				let buffer_size = self.input.samples() - self.sample_count;
-				if buffer_size <= 0 {
+				if buffer_size <= 0 && self.resampled.is_none() {
 					return Err(Error::from(FFError::Eof))
 				}
 
+				frame.make_writable()?;
 				frame.set_nb_samples(self.max_frame_size as i32);
-				self.cursor.storage_mut().storage_mut().shift_col_to(self.input, self.sample_count, cmp::min(self.max_frame_size, buffer_size));
-				frame_cap = self.copy_cursor_to_frame(frame)?;
+				frame_cap = match self.resampled {
+					None => {
+						frame.set_pts(self.sample_count as i64 + self.pts_offset);
+						self.cursor.storage_mut().storage_mut().shift_col_to(self.input, self.sample_count, cmp::min(self.max_frame_size, buffer_size));
+						self.copy_cursor_to_frame(frame)?
+					},
+					Some(resampled) => {
+						frame.set_pts(resampled as i64);
+						match self.resample_to_frame(frame)? {
+							0 => return Err(Error::from(FFError::Eof)),
+							count => count
+						}
+					}
+				};
 				frame.set_nb_samples(frame_cap);
 
 				match frame.send(&mut self.output.codec_ctx()) {
@@ -106,26 +201,54 @@ impl<'a: 'b, 'b, T, P, S> Writer<'a, 'b, T, P, S>
 			}
 		}
 
-		match packet.recieve(&mut self.output.codec_ctx()) {
-			Err(Error::FFM(FFError::Again)) => {},
+		while match packet.recieve(&mut self.output.codec_ctx()) {
+			Err(Error::FFM(FFError::Again)) => false,
+			Err(Error::FFM(FFError::Eof)) => false,
 			Err(e) => return Err(e),
-			_ => {}
+			_ => true
+		} {
+			self.output.write_packet(packet)?;
 		}
 
-		packet.write(&self.output.format_ctx())?;
-		packet.reset();
-
-		self.sample_count += frame_cap as usize;
+		match self.resampled {
+			None => self.sample_count += frame_cap as usize,
+			Some(ref mut resampled) => *resampled += frame_cap as usize,
+		}
 
 		Ok(())
 	}
 
+	// Feeds the converter about as much input as the rest of the frame needs until the frame is full,
+	// so the resampler buffers at most a frame. Once the input is used up its buffered tail is drained
+	fn resample_to_frame(&mut self, frame: &mut Frame) -> Result<i32, Error> {
+		let converter = self.converter.as_mut().ok_or("Resampling requires a converter")?;
+		let (src_rate, dst_rate) = (self.input.sample_rate() as i64, self.output.sample_rate() as i64);
+		let mut filled = 0;
+		while filled < frame.nb_samples() {
+			let buffer_size = self.input.samples() - self.sample_count;
+			if buffer_size == 0 {
+				match converter.flush_frame(frame, filled)? {
+					0 => break,
+					count => filled += count
+				}
+				continue;
+			}
+
+			let wanted = ((frame.nb_samples() - filled) as i64 * src_rate / dst_rate).max(1) as usize;
+			let count = cmp::min(wanted, buffer_size);
+			self.cursor.storage_mut().storage_mut().shift_col_to(self.input, self.sample_count, count);
+			self.sample_count += count;
+			filled += converter.convert_slice_at(&self.cursor, frame, filled)?;
+		}
+		Ok(filled)
+	}
+
 	pub fn copy_cursor_to_frame(&mut self, frame: &mut Frame) -> Result<i32, Error> {
 		Ok(match self.converter {
 			None => {
 				let sample_count = min(frame.nb_samples() as usize, self.cursor.samples());
-				match self.input.packing_type() {
-					SamplePacking::Interleaved => {
+				match (self.input.packing_type(), frame.sample_format().is_planar()) {
+					(SamplePacking::Interleaved, false) => {
 						unsafe {
 							ptr::copy_nonoverlapping(
 								self.cursor.as_row_ptr(0),
@@ -134,7 +257,7 @@ impl<'a: 'b, 'b, T, P, S> Writer<'a, 'b, T, P, S>
 							);
 						}
 					},
-					SamplePacking::Deinterleaved => {
+					(SamplePacking::Deinterleaved, true) => {
 						for c in 0..self.cursor.rows() {
 							unsafe {
 								ptr::copy_nonoverlapping(
@@ -144,6 +267,19 @@ impl<'a: 'b, 'b, T, P, S> Writer<'a, 'b, T, P, S>
 								);
 							}
 						}
+					},
+					(_, planar) => {
+						let (channels, src_stride) = (self.cursor.rows(), self.cursor.col_stride());
+						for c in 0..channels {
+							let src = self.cursor.as_row_ptr(c);
+							let (dst, dst_stride) = match planar {
+								true => (frame.data_mut_ptr(c) as *mut T, 1),
+								false => (unsafe { (frame.data_mut_ptr(0) as *mut T).add(c) }, channels)
+							};
+							for i in 0..sample_count {
+								unsafe { *dst.add(i * dst_stride) = *src.add(i * src_stride); }
+							}
+						}
 					}
 				}
 				sample_count as i32
@@ -154,3 +290,268 @@ impl<'a: 'b, 'b, T, P, S> Writer<'a, 'b, T, P, S>
 		})
 	}
 }
+
+// Samples per frame for encoders that take frames of any size
+const VARIABLE_FRAME_SIZE: i32 = 4096;
+
+// Converts pushed samples to the encoder format and buffers them in a fifo until they fill a frame of the
+// encoder, the last frame is padded with silence unless the encoder takes it short
+pub(crate) struct SampleQueue {
+	converter: Option<Converter>,
+	// Converted samples on their way into the fifo, with the number it holds
+	staging: Option<(Frame, i32)>,
+	fifo: AudioFifo,
+	format: AudioFormat,
+	frame_size: i32,
+	variable_size: bool,
+	short_frames: bool,
+}
+
+impl SampleQueue {
+	pub(crate) fn new(source: AudioFormat, encoder: &CodecContext, channel_map: Option<&[i32]>) -> Result<Self, Error> {
+		let format = encoder.audio_format();
+		let use_converter = format.sample_format != source.sample_format || format.sample_rate != source.sample_rate
+			|| channel_map.is_some();
+		let converter = match use_converter {
+			false => None,
+			true => Some(Converter::with_channel_map(source, format, channel_map)?)
+		};
+
+		let variable_size = encoder.frame_size() == 0;
+		let frame_size = match variable_size {
+			true => VARIABLE_FRAME_SIZE,
+			false => encoder.frame_size()
+		};
+		// Sized for a frame worth of input up front
+		let staging = match converter {
+			None => None,
+			Some(ref converter) => {
+				let capacity = converter.get_sample_count(frame_size).max(1);
+				Some((Frame::alloc(format, capacity)?, capacity))
+			}
+		};
+		Ok(SampleQueue {
+			converter, staging, fifo: AudioFifo::new(format.sample_format, format.channels(), frame_size)?,
+			format, frame_size, variable_size, short_frames: encoder.codec().accepts_short_frames()
+		})
+	}
+
+	pub(crate) fn format(&self) -> AudioFormat { self.format }
+
+	// Capacity the frames passed to `pop` need
+	pub(crate) fn frame_size(&self) -> i32 { self.frame_size }
+
+	// Converts `count` samples into the fifo, `planes` holds one pointer per plane of the source format
+	pub(crate) unsafe fn push(&mut self, planes: *const *const u8, count: i32) -> Result<(), Error> {
+		let converter = match self.converter {
+			None => {
+				self.fifo.write(planes, count)?;
+				return Ok(());
+			},
+			Some(ref mut converter) => converter
+		};
+
+		let capacity = converter.get_sample_count(count);
+		if capacity <= 0 {
+			return Ok(());
+		}
+		// Grows geometrically, so pushes of varying sizes settle on one staging frame
+		let allocated = self.staging.as_ref().map_or(0, |(_, c)| *c);
+		if allocated < capacity {
+			let capacity = capacity.max(allocated * 2);
+			self.staging = Some((Frame::alloc(self.format, capacity)?, capacity));
+		}
+
+		let (staging, _) = self.staging.as_mut().unwrap();
+		staging.set_nb_samples(capacity);
+		let converted = converter.convert(planes, count, (*staging.as_mut_ptr()).data.as_ptr(), capacity)?;
+		staging.set_nb_samples(converted);
+		self.fifo.write_frame(staging)?;
+		Ok(())
+	}
+
+	// Moves the samples still buffered by the resampler into the fifo
+	pub(crate) fn drain(&mut self) -> Result<(), Error> {
+		match self.converter.is_some() {
+			true => unsafe { self.push(ptr::null(), 0) },
+			false => Ok(())
+		}
+	}
+
+	// Fills `frame` with the next whole frame, on `finish` also with the remainder. False if none is ready
+	pub(crate) fn pop(&mut self, frame: &mut Frame, finish: bool) -> Result<bool, Error> {
+		let available = self.fifo.size();
+		if available == 0 || (available < self.frame_size && !finish && !self.variable_size) {
+			return Ok(false);
+		}
+
+		frame.make_writable()?;
+		frame.set_nb_samples(self.frame_size);
+		let count = self.fifo.read_frame(frame)?;
+		if count < self.frame_size {
+			match self.short_frames {
+				true => frame.set_nb_samples(count),
+				false => frame.set_silence(count, self.frame_size - count)?,
+			}
+		}
+		Ok(true)
+	}
+}
+
+// Encodes audio pushed in blocks of any size, e.g. while recording. Encoders with a fixed frame size get
+// exactly that many samples per frame
+pub struct StreamWriter<T, P>
+	where T: Sample, P: SamplePackingType
+{
+	output: Output,
+	queue: SampleQueue,
+	frame: Frame,
+	packet: Packet,
+	planes: Vec<*const u8>,
+	pts: i64,
+	channels: usize,
+	_phantoms: PhantomData<(T, P)>
+}
+
+impl<T, P> StreamWriter<T, P>
+	where T: Sample, P: SamplePackingType
+{
+	pub fn open(path: &str, channels: usize, sample_rate: i32) -> Result<Self, Error> {
+		Self::open_with(path, channels, sample_rate, WriterOptions::new())
+	}
+
+	pub fn open_with(path: &str, channels: usize, sample_rate: i32, options: WriterOptions) -> Result<Self, Error> {
+		let channel_layout = ChannelLayout::default(channels as i32);
+		let mut output = Output::open_with(
+			&path,
+			&options,
+			|i| pick_best_format(i, SampleFormat::from_type::<T, P>()),
+			channel_layout,
+			channels as i32,
+			sample_rate
+		)?;
+		output.format_ctx().dump(&path);
+		output.format_ctx().write_header(&mut Dictionary::from_pairs(&options.muxer_options)?)?;
+
+		let channel_map = options.channel_order.and_then(|o| o.inverse_permutation(channels));
+		let source = AudioFormat::new(channel_layout, SampleFormat::from_type::<T, P>(), sample_rate);
+		let queue = SampleQueue::new(
+			source, output.codec_ctx().ctx().ctx(), channel_map.as_ref().map(|m| m.as_slice())
+		)?;
+		let frame = Frame::alloc(queue.format(), queue.frame_size())?;
+		Ok(StreamWriter {
+			output, queue, frame, packet: Packet::empty(), planes: Vec::new(), pts: 0, channels, _phantoms: PhantomData
+		})
+	}
+
+	pub fn sdp(&self) -> Result<String, Error> { self.output.format_ctx().sdp() }
+
+	// Encodes the whole frames available, the rest is kept for the next push
+	pub fn push<S>(&mut self, audio: &S) -> Result<(), Error>
+		where S: AudioStorage<T, P>
+	{
+		if audio.channels() != self.channels {
+			return Err(Error::from(format!("Expected {} channels, got {}", self.channels, audio.channels())));
+		}
+
+		self.planes.clear();
+		match audio.packing_type() {
+			SamplePacking::Interleaved => self.planes.push(audio.as_row_ptr(0) as *const u8),
+			SamplePacking::Deinterleaved => {
+				for c in 0..audio.channels() {
+					self.planes.push(audio.as_row_ptr(c) as *const u8);
+				}
+			}
+		}
+		unsafe { self.queue.push(self.planes.as_ptr(), audio.samples() as i32)? };
+		self.encode_frames(false)
+	}
+
+	// Encodes the buffered samples and finalizes the file. Without it the file is left incomplete
+	pub fn finish(mut self) -> Result<(), Error> {
+		self.queue.drain()?;
+		self.encode_frames(true)?;
+		self.encode(true)?;
+
+		unsafe { ffm_op!(av_write_trailer(self.output.format_ctx().as_mut_ptr()))? };
+		Ok(())
+	}
+
+	fn encode_frames(&mut self, finish: bool) -> Result<(), Error> {
+		while self.queue.pop(&mut self.frame, finish)? {
+			self.frame.set_pts(self.pts);
+			self.pts += self.frame.nb_samples() as i64;
+			self.encode(false)?;
+		}
+		Ok(())
+	}
+
+	// Sends the current frame or the flush and writes the packets that come out
+	fn encode(&mut self, flush: bool) -> Result<(), Error> {
+		let sent = match flush {
+			false => self.frame.send(&mut self.output.codec_ctx()),
+			true => Frame::send_flush(&mut self.output.codec_ctx()),
+		};
+		match sent {
+			Err(Error::FFM(FFError::Again)) => {},
+			Err(e) => return Err(e),
+			_ => {}
+		}
+
+		while match self.packet.recieve(&mut self.output.codec_ctx()) {
+			Err(Error::FFM(FFError::Again)) => false,
+			Err(Error::FFM(FFError::Eof)) => false,
+			Err(e) => return Err(e),
+			_ => true
+		} {
+			self.output.write_packet(&mut self.packet)?;
+		}
+		Ok(())
+	}
+}
+
+// Interleaved samples, as handed out by most audio callbacks
+pub type Block<T> = AudioInterleaved<T, Dynamic, Dynamic>;
+
+// Encodes blocks received from a channel on its own thread, so an audio callback only ever sends. The file
+// is finalized once every sender is dropped, if encoding fails the receiver is dropped and sends start failing
+pub struct ChannelWriter {
+	handle: JoinHandle<Result<usize, Error>>,
+}
+
+impl ChannelWriter {
+	pub fn spawn<T, P, S>(path: &str, channels: usize, sample_rate: i32, options: WriterOptions, blocks: Receiver<S>)
+		-> Result<Self, Error>
+		where T: Sample + 'static, P: SamplePackingType + 'static, S: AudioStorage<T, P> + Send + 'static
+	{
+		let (opened_sender, opened) = sync_channel(1);
+
+		let path = path.to_string();
+		let handle = thread::spawn(move || {
+			let mut writer = match StreamWriter::<T, P>::open_with(&path, channels, sample_rate, options) {
+				Err(e) => {
+					opened_sender.send(Err(e)).unwrap_or(());
+					return Ok(0);
+				},
+				Ok(writer) => writer
+			};
+			opened_sender.send(Ok(())).unwrap_or(());
+
+			let mut samples = 0;
+			for block in blocks.iter() {
+				writer.push(&block)?;
+				samples += block.samples();
+			}
+			writer.finish()?;
+			Ok(samples)
+		});
+
+		opened.recv().map_err(|_| Error::from("The writer thread exited"))??;
+		Ok(ChannelWriter { handle })
+	}
+
+	// Waits until the channel is closed and the file is finalized, returns the samples written
+	pub fn join(self) -> Result<usize, Error> {
+		self.handle.join().map_err(|_| Error::from("The writer thread panicked"))?
+	}
+}
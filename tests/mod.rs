@@ -0,0 +1,179 @@
+use std::path::PathBuf;
+use litaudio::*;
+use litcontainers::*;
+use litaudioio::*;
+use litaudioio::options::*;
+use litaudioio::samples::*;
+
+fn tmp_path(name: &str) -> PathBuf {
+	std::env::temp_dir().join(format!("litaudioio_{}", name))
+}
+
+fn fixture_path(name: &str) -> PathBuf {
+	PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+// A sine of a different frequency in every channel, so swapped channels are told apart
+fn tone(channels: usize, samples: usize, sample_rate: i32) -> AudioDeinterleaved<f32, Dynamic, Dynamic> {
+	let mut audio: AudioDeinterleaved<f32, Dynamic, Dynamic> = AudioContainer::zeros(
+		Size::new(D!(channels), D!(samples))
+	).into();
+	audio.set_sample_rate(sample_rate);
+	for c in 0..channels {
+		map_channel(&mut audio, c, |i, _| (i as f32 * 0.01 * (c + 1) as f32).sin() * 0.5);
+	}
+	audio
+}
+
+fn assert_channel_eq<P, S, SD>(actual: &S, actual_channel: usize, expected: &SD, expected_channel: usize)
+	where P: SamplePackingType, S: AudioStorage<f32, P>, SD: AudioStorage<f32, P>
+{
+	let mismatch = channel_iter(actual, actual_channel).zip(channel_iter(expected, expected_channel))
+		.position(|(a, e)| (a - e).abs() > 1e-4);
+	assert_eq!(mismatch, None, "channel {} differs from channel {}", actual_channel, expected_channel);
+}
+
+#[test]
+fn wavpack_round_trip() {
+	let path = tmp_path("round_trip.wv");
+	let audio = tone(2, 44100, 44100);
+	write_audio_with(&path, &audio, WriterOptions::new().wavpack(3)).unwrap();
+
+	let decoded: AudioDeinterleaved<f32, Dynamic, Dynamic> = read_audio(&path).unwrap();
+	assert_eq!(decoded.channels(), 2);
+	assert_eq!(decoded.sample_rate(), 44100);
+	assert_eq!(decoded.samples(), audio.samples());
+	for c in 0..2 {
+		assert_channel_eq(&decoded, c, &audio, c);
+	}
+}
+
+#[test]
+fn surround_wav_round_trip() {
+	let path = tmp_path("surround.wav");
+	let audio = tone(6, 4800, 48000);
+	write_audio(&path, &audio).unwrap();
+
+	let decoded: AudioDeinterleaved<f32, Dynamic, Dynamic> = read_audio(&path).unwrap();
+	assert_eq!(decoded.channels(), 6);
+	assert_eq!(decoded.samples(), audio.samples());
+	for c in 0..6 {
+		assert_channel_eq(&decoded, c, &audio, c);
+	}
+}
+
+#[test]
+fn film_order_wav_round_trip() {
+	let path = tmp_path("film.wav");
+	let audio = tone(6, 4800, 48000);
+	write_audio_with(&path, &audio, WriterOptions::new().channel_order(ChannelOrder::Film)).unwrap();
+
+	// Film channel i is stored as native channel permutation[i]
+	let native: AudioDeinterleaved<f32, Dynamic, Dynamic> = read_audio(&path).unwrap();
+	let permutation = ChannelOrder::Film.permutation(6).unwrap();
+	for c in 0..6 {
+		assert_channel_eq(&native, permutation[c] as usize, &audio, c);
+	}
+
+	let film: AudioDeinterleaved<f32, Dynamic, Dynamic> = read_audio_with(
+		&path, ReaderOptions::new().channel_order(ChannelOrder::Film)
+	).unwrap();
+	for c in 0..6 {
+		assert_channel_eq(&film, c, &audio, c);
+	}
+}
+
+// A 440 Hz sine, speech codecs don't keep the low frequencies of `tone`
+fn voice_band_tone(samples: usize, sample_rate: i32) -> AudioDeinterleaved<f32, Dynamic, Dynamic> {
+	let mut audio = tone(1, samples, sample_rate);
+	let step = 2. * std::f32::consts::PI * 440. / sample_rate as f32;
+	map_channel(&mut audio, 0, |i, _| (i as f32 * step).sin() * 0.5);
+	audio
+}
+
+// Encodes a second of 8 kHz mono with `codec` and checks that it decodes to about the same signal
+fn telephony_round_trip(name: &str, codec: &str, max_error: Option<f32>) {
+	let path = tmp_path(name);
+	let audio = voice_band_tone(8000, 8000);
+	write_audio_with(&path, &audio, WriterOptions::new().codec(codec)).unwrap();
+
+	let decoded: AudioDeinterleaved<f32, Dynamic, Dynamic> = read_audio(&path).unwrap();
+	assert_eq!(decoded.channels(), 1);
+	assert_eq!(decoded.sample_rate(), 8000);
+	// Block based codecs pad the last block, codecs with a delay may also drop a frame
+	assert!((decoded.samples() as i64 - audio.samples() as i64).abs() < 800, "decoded {} samples", decoded.samples());
+
+	let peak = channel_iter(&decoded, 0).fold(0f32, |peak, v| peak.max(v.abs()));
+	assert!(peak > 0.25, "decoded peak is {}", peak);
+	if let Some(max_error) = max_error {
+		// ADPCM starts with the smallest step, it takes a few dozen samples to track the signal
+		let error = channel_iter(&decoded, 0).zip(channel_iter(&audio, 0)).skip(256)
+			.fold(0f32, |error, (a, e)| error.max((a - e).abs()));
+		assert!(error < max_error, "largest error is {}", error);
+	}
+}
+
+#[test]
+fn ima_adpcm_wav_round_trip() {
+	telephony_round_trip("ima_adpcm.wav", "adpcm_ima_wav", Some(0.1));
+}
+
+#[test]
+fn ms_adpcm_wav_round_trip() {
+	telephony_round_trip("ms_adpcm.wav", "adpcm_ms", Some(0.1));
+}
+
+// FFmpeg only encodes GSM and Speex through libgsm and libspeex, builds without them skip these
+#[test]
+fn gsm_wav_round_trip() {
+	if build_info().has_encoder("libgsm_ms") {
+		telephony_round_trip("gsm.wav", "libgsm_ms", None);
+	}
+}
+
+#[test]
+fn speex_round_trip() {
+	if build_info().has_encoder("libspeex") {
+		telephony_round_trip("speex.spx", "libspeex", None);
+	}
+}
+
+// Silent GSM 6.10 decoded by FFmpeg's own decoders, 10 WAV blocks of 320 samples and 20 raw frames of 160
+fn assert_gsm_fixture(decoded: &AudioDeinterleaved<f32, Dynamic, Dynamic>) {
+	assert_eq!(decoded.channels(), 1);
+	assert_eq!(decoded.sample_rate(), 8000);
+	assert_eq!(decoded.samples(), 3200);
+	assert!(channel_iter(decoded, 0).all(|v| v.is_finite() && v.abs() <= 1.));
+}
+
+#[test]
+fn gsm_wav_fixture() {
+	let path = fixture_path("gsm_ms.wav");
+	assert_eq!(litaudioio::info::probe(path.to_str().unwrap()).unwrap().codec_name, "gsm_ms");
+	assert_gsm_fixture(&read_audio(&path).unwrap());
+}
+
+#[test]
+fn raw_gsm_fixture() {
+	let path = fixture_path("raw.gsm");
+	assert_gsm_fixture(&read_audio_with(&path, ReaderOptions::new().raw_gsm()).unwrap());
+}
+
+#[test]
+fn telephony_support_matches_build() {
+	let build = build_info();
+	let support = litaudioio::info::telephony_support();
+	assert_eq!(
+		support.iter().map(|s| s.codec_name).collect::<Vec<_>>(),
+		["adpcm_ima_wav", "adpcm_ms", "gsm", "gsm_ms", "speex"]
+	);
+	for codec in &support {
+		assert_eq!(codec.is_supported(), codec.decoder.is_some());
+		if let Some(decoder) = codec.decoder {
+			assert!(build.has_decoder(decoder), "{} is not listed in the build info", decoder);
+		}
+	}
+	// FFmpeg decodes ADPCM and GSM natively, Speex only through libspeex before 4.4
+	assert!(support[..4].iter().all(|s| s.is_supported()));
+	assert_eq!(support[4].is_supported(), build.has_decoder("speex") || build.has_decoder("libspeex"));
+}